@@ -0,0 +1,264 @@
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Component, Path, PathBuf};
+
+use itertools::Itertools;
+
+use crate::cli::ReadArgs;
+use crate::error::{Diagnostics, ShipError};
+use crate::models::report::{OutputFormat, ReportOptions};
+use crate::writer::{write_portfolio_as, write_report_as};
+
+const EXTENSIONS: [&str; 3] = ["txt", "csv", "json"];
+
+pub enum BatchOutput {
+    Stdout,
+    Directory(PathBuf),
+    Combined(PathBuf),
+}
+
+pub struct BatchOptions {
+    pub inputs: Vec<PathBuf>,
+    pub output: BatchOutput,
+    /// How each file is read and built, as for a single input.
+    pub read: ReadArgs,
+    /// How each report is computed, as for `report`.
+    pub report: ReportOptions,
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
+impl BatchOptions {
+    /// Expands directories into the .txt, .csv and .json files directly inside them. CSV and HTML
+    /// reports don't join into one output, so they need a directory.
+    pub fn from_paths(paths: &[PathBuf], output: BatchOutput, read: ReadArgs, report: ReportOptions, format: OutputFormat) -> Result<BatchOptions, ShipError> {
+        if matches!(format, OutputFormat::Csv | OutputFormat::Html) && !matches!(output, BatchOutput::Directory(..)) {
+            return Err(ShipError::Config("'batch' writes csv and html reports with --out-dir only.".to_string()));
+        }
+
+        Ok(BatchOptions { inputs: collect_inputs(paths)?, output, read, report, format })
+    }
+}
+
+impl BatchSummary {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+fn collect_inputs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, ShipError> {
+    let mut inputs = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = fs::read_dir(path)
+                .map_err(|err| ShipError::Config(format!("Unable to read {}: {}", path.display(), err)))?
+                .filter_map(|entry| entry.ok().map(|x| x.path()))
+                .filter(|x| x.is_file() && has_known_extension(x))
+                .collect();
+            entries.sort();
+            inputs.append(&mut entries);
+        } else {
+            inputs.push(path.clone());
+        }
+    }
+
+    Ok(inputs)
+}
+
+fn has_known_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .map(|x| EXTENSIONS.contains(&x))
+        .unwrap_or(false)
+}
+
+/// Reads and builds one file the way `report` does: one report, or a portfolio when the file holds
+/// more than one tracking number. Skipped lines are listed on stderr.
+fn process_file(path: &Path, options: &BatchOptions) -> Result<String, ShipError> {
+    let mut diagnostics = Diagnostics::new(options.read.strict);
+    let shipments = options.read.load(&path.to_string_lossy(), &mut diagnostics)?;
+    eprint!("{}", diagnostics.summary());
+
+    match shipments.as_slice() {
+        [shipment] => write_report_as(&shipment.records, &options.report, options.format),
+        _ => write_portfolio_as(&shipments, &options.report, options.format),
+    }
+}
+
+/// Every report in one output: a heading per file, or one JSON object keyed by file.
+fn combine(reports: &[(String, String)], format: OutputFormat) -> Result<String, ShipError> {
+    match format {
+        OutputFormat::Json => {
+            let mut object = serde_json::Map::new();
+            for (name, report) in reports {
+                object.insert(name.clone(), serde_json::from_str(report)?);
+            }
+            Ok(serde_json::to_string_pretty(&object)? + "\n")
+        },
+        _ => Ok(reports.iter()
+            .map(|(name, report)| format!("==== {} ====\n{}\n", name, report.trim_start_matches('\n')))
+            .collect()),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_else(|| "shipment".to_string())
+}
+
+fn path_parts(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|x| match x {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// One `<input>.report.<extension>` per input. Inputs that share a file name keep as much of their
+/// path as tells them apart (`a/x.txt` and `b/x.txt` write `a_x.txt.report.txt` and `b_x.txt.report.txt`);
+/// two inputs that would still write the same report are an error.
+fn report_paths(dir: &Path, inputs: &[PathBuf], format: OutputFormat) -> Result<Vec<PathBuf>, ShipError> {
+    let names: Vec<String> = inputs.iter()
+        .map(|path| {
+            let clashing: Vec<Vec<String>> = inputs.iter()
+                .filter(|x| file_name(x) == file_name(path))
+                .map(|x| path_parts(x))
+                .collect();
+            let parts = path_parts(path);
+            let shortest = clashing.iter().map(Vec::len).min().unwrap_or_default();
+            let shared = (0..shortest).take_while(|i| clashing.iter().all(|x| x[*i] == clashing[0][*i])).count();
+
+            match parts.len() {
+                0 => file_name(path),
+                len => parts[shared.min(len - 1)..].join("_"),
+            }
+        })
+        .collect();
+
+    if let Some(name) = names.iter().duplicates().next() {
+        return Err(ShipError::Config(format!("More than one input would write {}.report.{}.", name, format.extension())));
+    }
+
+    Ok(names.iter().map(|x| dir.join(format!("{}.report.{}", x, format.extension()))).collect())
+}
+
+pub fn run_batch(options: &BatchOptions) -> Result<BatchSummary, ShipError> {
+    let mut summary = BatchSummary::default();
+    let mut reports = Vec::new();
+
+    let report_paths = match &options.output {
+        BatchOutput::Directory(dir) => {
+            fs::create_dir_all(dir)?;
+            report_paths(dir, &options.inputs, options.format)?
+        },
+        _ => Vec::new(),
+    };
+
+    for (index, path) in options.inputs.iter().enumerate() {
+        let name = path.display().to_string();
+        let report = match process_file(path, options) {
+            Ok(report) => report,
+            Err(err) => {
+                summary.failed.push((name, err.to_string()));
+                continue;
+            }
+        };
+
+        match &options.output {
+            BatchOutput::Directory(..) => {
+                let mut file = File::create(&report_paths[index])?;
+                file.write_all(report.as_bytes())?;
+            },
+            _ => reports.push((name.clone(), report)),
+        }
+        summary.succeeded.push(name);
+    }
+
+    match &options.output {
+        BatchOutput::Stdout => print!("{}", combine(&reports, options.format)?),
+        BatchOutput::Combined(filename) => {
+            let mut file = File::create(filename)?;
+            file.write_all(combine(&reports, options.format)?.as_bytes())?;
+        },
+        BatchOutput::Directory(..) => (),
+    }
+
+    Ok(summary)
+}
+
+pub fn write_summary(summary: &BatchSummary) -> String {
+    let mut contents = format!(
+        "Processed {} files: {} succeeded, {} failed\n",
+        summary.succeeded.len() + summary.failed.len(),
+        summary.succeeded.len(),
+        summary.failed.len(),
+        );
+
+    for (name, err) in &summary.failed {
+        contents.push_str(&format!("  FAILED {} - {}\n", name, err));
+    }
+
+    contents
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::models::report::Section;
+
+    #[test]
+    fn collect_directory() {
+        let inputs = collect_inputs(&[PathBuf::from("tests/data")]).unwrap();
+        assert!(inputs.contains(&PathBuf::from("tests/data/test_data.csv")));
+        assert!(inputs.iter().all(|x| has_known_extension(x)));
+    }
+
+    #[test]
+    fn report_per_input_file() {
+        let dir = Path::new("reports");
+        let paths = report_paths(dir, &[PathBuf::from("a/test_data.txt"), PathBuf::from("a/test_data.csv")], OutputFormat::Text).unwrap();
+        assert_eq!(paths, vec![dir.join("test_data.txt.report.txt"), dir.join("test_data.csv.report.txt")]);
+
+        let paths = report_paths(dir, &[PathBuf::from("a/x.txt"), PathBuf::from("b/x.txt"), PathBuf::from("y.txt")], OutputFormat::Text).unwrap();
+        assert_eq!(paths, vec![dir.join("a_x.txt.report.txt"), dir.join("b_x.txt.report.txt"), dir.join("y.txt.report.txt")]);
+        let paths = report_paths(dir, &[PathBuf::from("/data/a/x.txt"), PathBuf::from("/data/b/x.txt")], OutputFormat::Text).unwrap();
+        assert_eq!(paths, vec![dir.join("a_x.txt.report.txt"), dir.join("b_x.txt.report.txt")]);
+        assert!(report_paths(dir, &[PathBuf::from("a/x.txt"), PathBuf::from("a/x.txt")], OutputFormat::Text).is_err());        assert_eq!(report_paths(dir, &[PathBuf::from("y.txt")], OutputFormat::Json).unwrap(), vec![dir.join("y.txt.report.json")]);
+    }
+
+    #[test]
+    fn combined_formats() {
+        let output = std::env::temp_dir().join("shiptracker_batch_test.json");
+        let report = ReportOptions { sections: vec![Section::Total], ..ReportOptions::default() };
+        let options = BatchOptions::from_paths(&[PathBuf::from("tests/data/test_data.txt")], BatchOutput::Combined(output.clone()), ReadArgs::default(), report.clone(), OutputFormat::Json).unwrap();
+        run_batch(&options).unwrap();
+
+        let combined: serde_json::Value = serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(combined["tests/data/test_data.txt"]["total_transit_minutes"], 8332);
+        assert!(BatchOptions::from_paths(&[], BatchOutput::Stdout, ReadArgs::default(), report, OutputFormat::Csv).is_err());
+    }
+
+    #[test]
+    fn failures_do_not_abort() {
+        let options = BatchOptions {
+            inputs: vec![PathBuf::from("tests/data/missing.txt"), PathBuf::from("tests/data/test_data.txt")],
+            output: BatchOutput::Combined(std::env::temp_dir().join("shiptracker_batch_test.txt")),
+            read: ReadArgs::default(),
+            report: ReportOptions::default(),
+            format: OutputFormat::Text,
+        };
+        let summary = run_batch(&options).unwrap();
+        assert_eq!(summary.succeeded, vec!["tests/data/test_data.txt".to_string()]);
+        assert_eq!(summary.failed.len(), 1);
+    }
+}
@@ -1,36 +1,98 @@
-use std::error::Error;
-use regex::{Regex, RegexSet};
-use chrono::NaiveDateTime;
-use celes::Country;
-use std::str::FromStr;
+use std::ops::Range;
+use chrono::{NaiveDateTime, Offset, Utc};
+use itertools::Itertools;
+use regex::Regex;
 
+use crate::classifier::Classifier;
+use crate::error::{Diagnostics, ShipError};
+use crate::gazetteer::Gazetteer;
+use crate::models::event_kind::EventKind;
+use crate::models::location::{Location, Provenance};
 use crate::models::record::Record;
+use crate::models::shipment::Shipment;
+use crate::reader::RawData;
+use crate::resolver::CountryResolver;
+use crate::timestamp::{Timestamp, TimestampParser};
+use crate::timezone::{offset_for_country, LocalZone};
 
-fn parse_raw_records(data: Vec<String>) -> Result<Vec<Record>, Box<dyn Error>> {
-    let pattern_set = RegexSet::new(&[
-        r"(\d{4})-(\d{2})-(\d{2})",
-        r"(\d{2}):(\d{2}):(\d{2})",
-        r"([a-zA-Z].*$)",
-    ]).unwrap();
+/// How records with no recognisable country borrow one from their neighbours. Every strategy but
+/// `LeaveUnknown` falls back to the other side at the edges, so a shipment with any known country
+/// ends up with no gaps.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum FillStrategy {
+    /// The last known location before the gap.
+    #[default]
+    Forward,
+    /// The first known location after the gap.
+    Backward,
+    /// Whichever of the two is closer in time, the earlier one on a tie.
+    Nearest,
+    /// Leave the gap as an explicit unknown location.
+    LeaveUnknown,
+}
 
-    let regexes: Vec<Regex> = pattern_set.patterns().iter()
-        .map(|pattern| Regex::new(pattern).unwrap())
-        .collect();
+#[derive(Debug, Default, Clone)]
+pub struct BuildOptions {
+    pub time_format: Option<TimestampParser>,
+    pub classifier: Classifier,
+    /// Pulls a tracking number out of each status line. The first capture group is used when there is one.
+    pub tracking: Option<Regex>,
+    pub fill: FillStrategy,
+    /// Country aliases, tried before the built-in dictionary.
+    pub resolver: CountryResolver,
+    /// Cities, hubs and airport codes for scans that name no country.
+    pub gazetteer: Gazetteer,
+    /// Reads timestamps without an offset in this zone rather than their scan's country.
+    pub timezone: Option<LocalZone>,
+}
+
+impl FillStrategy {
+    pub fn new(name: &str) -> Option<FillStrategy> {
+        match name {
+            "forward" => Some(FillStrategy::Forward),
+            "backward" => Some(FillStrategy::Backward),
+            "nearest" => Some(FillStrategy::Nearest),
+            "unknown" | "leave-unknown" => Some(FillStrategy::LeaveUnknown),
+            _ => None,
+        }
+    }
+}
 
+fn strip_timestamp(line: &str, range: Range<usize>) -> String {
+    let delimiters: &[char] = &[' ', ',', ';', '\t', '|'];
+    let before = line[..range.start].trim_end_matches(delimiters).trim();
+    let after = line[range.end..].trim_start_matches(delimiters).trim();
+
+    [before, after].iter()
+        .filter(|x| !x.is_empty())
+        .join(" ")
+}
+
+fn parse_raw_record(line: &str, parser: &TimestampParser) -> Result<Record, ShipError> {
+    let (timestamp, range) = parser.find(line)
+        .ok_or_else(|| ShipError::UnrecognisedRecord(line.to_string()))?;
+    let status = strip_timestamp(line, range);
+    if status.is_empty() {
+        return Err(ShipError::MissingStatus(line.to_string()));
+    }
+
+    build_record(timestamp, status)
+}
+
+fn parse_raw_records(data: Vec<(usize, String)>, parser: &TimestampParser, diagnostics: &mut Diagnostics) -> Result<Vec<Record>, ShipError> {
     let mut records: Vec<Record> = Vec::new();
 
-    for line in data {
-        let matches: Vec<&str> = pattern_set.matches(&line).into_iter()
-            .map(|match_index| &regexes[match_index])
-            .map(|pattern| pattern.find(&line).unwrap().as_str())
-            .collect();
-        let (date, time, status) = (matches[0].to_string(), matches[1].to_string(), matches[2].to_string());
-        let datetime = parse_naive_datetime(format!("{} {}", date, time).as_str()).unwrap();
-        let record = Record::new(datetime, status).unwrap();
-        records.push(record);
+    for (line_number, line) in data {
+        match parse_raw_record(&line, parser) {
+            Ok(mut record) => {
+                record.set_line(Some(line_number));
+                records.push(record);
+            },
+            Err(err) => diagnostics.report(err.at_line(line_number))?,
+        }
     }
 
-    records.sort_by_key(|x| x.datetime);
+    records.sort_by_key(|x| x.local_datetime());
 
     Ok(records)
 }
@@ -39,63 +101,399 @@ fn parse_naive_datetime(datetime_str: &str) -> Result<NaiveDateTime, chrono::Par
     NaiveDateTime::parse_from_str(datetime_str, "%Y-%m-%d %H:%M:%S")
 }
 
-fn parse_raw_locale(status_str: &str) -> Option<String> {
-    let delimiters = &['.', ',', '-', ';', ' '];
-    let mut reverse_split: Vec<&str> = status_str.rsplitn(3, delimiters).collect();
+pub(crate) fn parse_timestamp(timestamp: &str, parser: Option<&TimestampParser>) -> Result<Timestamp, ShipError> {
+    let parsed = match parser {
+        Some(parser) => parser.parse(timestamp),
+        None => parse_naive_datetime(timestamp.trim()).ok()
+            .map(|local| Timestamp { local, offset: None })
+            .or_else(|| TimestampParser::parse_any(timestamp)),
+    };
+
+    parsed.ok_or_else(|| ShipError::InvalidTimestamp(timestamp.to_string()))
+}
+
+pub(crate) fn build_record(timestamp: Timestamp, status: String) -> Result<Record, ShipError> {
+    let mut record = Record::new(timestamp.local, status)?;
+    if let Some(offset) = timestamp.offset {
+        record.set_offset(offset, true);
+    }
+
+    Ok(record)
+}
 
-    reverse_split.retain(|x| !x.is_empty());
+pub(crate) fn parse_location_field(location: &str, options: &BuildOptions) -> Option<Location> {
+    parse_location_field_with(location, &options.resolver, &options.gazetteer)
+}
 
-    let raw_locale: Option<String> = match Country::from_str(reverse_split[0]) {
-        Ok(x) => Some(x.to_string()),
-        Err(..) => None
+/// Parses a field that holds nothing but a location, e.g. a CSV location column. A field with no
+/// country at all is kept as the city; so is the text in front of a bare country ("Miami, FL").
+fn parse_location_field_with(location: &str, resolver: &CountryResolver, gazetteer: &Gazetteer) -> Option<Location> {
+    let location = location.trim();
+    if location.is_empty() {
+        return None;
+    }
+
+    if let Some(found) = resolver.resolve(location) {
+        return Some(Location {
+            region: found.region,
+            country: Some(found.country),
+            provenance: Some(Provenance::new(found.rule, &found.token)),
+            ..Location::default()
+        });
+    }
+
+    match parse_raw_locale(location, resolver, gazetteer) {
+        Some((mut parsed, prefix)) => {
+            let delimiters: &[char] = &['.', ',', '-', ';', ' '];
+            let prefix = prefix.trim_matches(delimiters);
+            let prefix = parsed.region.as_deref()
+                .and_then(|x| prefix.strip_suffix(x))
+                .unwrap_or(prefix)
+                .trim_matches(delimiters);
+            if parsed.facility.is_none() && parsed.city.is_none() && parsed.postal_code.is_none() && !prefix.is_empty() {
+                parsed.city = Some(prefix.to_string());
+            }
+            Some(parsed)
+        },
+        None => Some(Location { city: Some(location.to_string()), ..Location::default() }),
+    }
+}
+
+fn is_region_code(token: &str) -> bool {
+    token.len() == 2 && token.chars().all(|x| x.is_ascii_uppercase())
+}
+
+fn is_postal_code(token: &str) -> bool {
+    (4..=6).contains(&token.len()) && token.chars().all(|x| x.is_ascii_digit())
+}
+
+fn is_upper_word(word: &str) -> bool {
+    word.chars().filter(|x| x.is_alphabetic()).count() >= 2
+        && word.chars().all(|x| !x.is_alphabetic() || x.is_uppercase())
+}
+
+/// Splits the text in front of a country token into facility, city, region and postal code.
+/// Carriers usually print places in capitals ("Departed Facility in CINCINNATI HUB,OH"), so the
+/// trailing run of upper-case words is taken as the place; otherwise a short segment after the
+/// last ',' or ';' is used ("customs office; Maimi").
+fn parse_place(prefix: &str) -> Location {
+    let delimiters: &[char] = &['.', ',', '-', ';', ' '];
+    let mut location = Location::default();
+    let mut rest = prefix.trim_end_matches(delimiters);
+
+    for _ in 0..2 {
+        let start = rest.rfind(delimiters).map(|x| x + 1).unwrap_or(0);
+        let token = &rest[start..];
+        if start > 0 && location.region.is_none() && is_region_code(token) {
+            location.region = Some(token.to_string());
+        } else if location.postal_code.is_none() && is_postal_code(token) {
+            location.postal_code = Some(token.to_string());
+        } else {
+            break;
+        }
+        rest = rest[..start].trim_end_matches(delimiters);
+    }
+
+    let segment_start = rest.rfind(&[',', ';'][..]).map(|x| x + 1);
+    let segment = rest[segment_start.unwrap_or(0)..].trim();
+    let words: Vec<&str> = segment.split_whitespace().collect();
+    let upper_run = words.iter().rev().take_while(|x| is_upper_word(x)).count();
+
+    let place = if upper_run > 0 {
+        Some(words[words.len() - upper_run..].join(" "))
+    } else if segment_start.is_some() && !words.is_empty() && words.len() <= 3
+        && words.iter().all(|x| x.starts_with(char::is_uppercase)) {
+        Some(words.join(" "))
+    } else {
+        None
     };
 
-    raw_locale
+    if let Some(place) = place {
+        let keywords = ["HUB", "GATEWAY", "FACILITY", "DEPOT", "CENTER", "CENTRE", "TERMINAL", "WAREHOUSE", "AIRPORT", "STATION"];
+        let city: Vec<&str> = place.split_whitespace()
+            .filter(|x| !keywords.contains(&x.to_uppercase().as_str()))
+            .collect();
+
+        if city.len() != place.split_whitespace().count() {
+            location.facility = Some(place.clone());
+        }
+        if !city.is_empty() {
+            location.city = Some(city.join(" "));
+        }
+    }
+
+    location
+}
+
+/// The word in front of a country token when it reads as a place: a capitalised word ("Boston, MA")
+/// or a preposition ("Delivered to ME").
+fn place_before(prefix: &str) -> Option<&str> {
+    let delimiters: &[char] = &['.', ',', '-', ';', ' '];
+    let word = prefix.trim_end_matches(delimiters).rsplit(delimiters).next()?;
+    let is_preposition = ["at", "in", "to", "from"].contains(&word.to_lowercase().as_str());
+
+    (word.starts_with(char::is_uppercase) || is_preposition).then_some(word)
+}
+
+/// Resolves the country from the last one to three words of the text, longest first, so "PAPUA NEW
+/// GUINEA" isn't read as "GUINEA". Returns the location and the text in front of the country.
+fn parse_raw_locale<'a>(status_str: &'a str, resolver: &CountryResolver, gazetteer: &Gazetteer) -> Option<(Location, &'a str)> {
+    let delimiters: &[char] = &['.', ',', '-', ';', ' '];
+    let trimmed = status_str.trim_end_matches(delimiters);
+    let mut starts = Vec::new();
+    let mut rest = trimmed;
+
+    for _ in 0..3 {
+        let start = rest.rfind(delimiters).map(|x| x + 1).unwrap_or(0);
+        starts.push(start);
+        if start == 0 {
+            break;
+        }
+        rest = rest[..start].trim_end_matches(delimiters);
+    }
+
+    let (start, found) = starts.iter().rev()
+        .find_map(|start| resolver.resolve(&trimmed[*start..]).map(|found| (*start, found)))?;
+    let mut location = parse_place(&trimmed[..start]);
+    let found = match place_before(&trimmed[..start]) {
+        Some(word) => resolver.after_place(found, location.city.as_deref().unwrap_or(word), gazetteer),
+        None => found,
+    };
+    location.country = Some(found.country);
+    location.provenance = Some(Provenance::new(found.rule, &found.token));
+    if found.region.is_some() {
+        location.region = found.region;
+    }
+
+    Some((location, &trimmed[..start]))
+}
+
+/// Looks a status with no country token up in the gazetteer. The place's city replaces whatever
+/// was read as the city ("CVG" in "CVG GATEWAY"), while the facility is kept.
+fn locate_place(status: &str, gazetteer: &Gazetteer) -> Option<Location> {
+    let found = gazetteer.find(status)?;
+    let mut location = parse_place(status);
+    location.city = Some(found.place.city.to_string());
+    location.country = Some(found.place.country);
+    location.provenance = Some(Provenance::new(found.rule, &found.token));
+    location.coordinates = Some(found.place.coordinates);
+
+    Some(location)
+}
+
+/// A location given without a country, e.g. a JSON event with only a city and region, may still name
+/// one through a state code, a user alias or the gazetteer.
+fn resolve_partial(location: &Location, options: &BuildOptions) -> Option<Location> {
+    [&location.region, &location.city].into_iter()
+        .flatten()
+        .find_map(|x| options.resolver.resolve(x))
+        .map(|found| Location {
+            country: Some(found.country),
+            provenance: Some(Provenance::new(found.rule, &found.token)),
+            ..Location::default()
+        })
+        .or_else(|| location.city.as_ref().and_then(|x| locate_place(x, &options.gazetteer)))
 }
 
-fn set_locales(records: Vec<Record>) -> Result<Vec<Record>, Box<dyn Error>> {
+fn set_locales(records: Vec<Record>, options: &BuildOptions) -> Result<Vec<Record>, ShipError> {
     let mut locale_records = Vec::new();
 
     for mut rec in records {
-        let new_locale = parse_raw_locale(&rec.status);
-        if new_locale.is_some() {
-            rec.set_locale(new_locale);
-            locale_records.push(rec)
-        } else {
-            rec.set_locale(None);
-            locale_records.push(rec);
+        let parsed = parse_raw_locale(&rec.status, &options.resolver, &options.gazetteer).map(|(location, _)| location);
+        let parsed = match rec.location.as_ref() {
+            Some(location) if location.country.is_none() => parsed.or_else(|| resolve_partial(location, options)),
+            _ => parsed,
+        };
+        let parsed = parsed.or_else(|| locate_place(&rec.status, &options.gazetteer));
+
+        match (rec.location.as_mut(), parsed) {
+            (Some(location), Some(parsed)) if location.country.is_none() => location.fill_from(&parsed),
+            (Some(..), _) => {},
+            (None, parsed) => rec.set_location(parsed),
         }
+        if let Some(location) = rec.location.as_mut() {
+            if let (None, Some(city), Some(country)) = (location.coordinates, &location.city, &location.country) {
+                location.coordinates = options.gazetteer.coordinates(city, country);
+            }
+        }
+        locale_records.push(rec);
     }
 
     Ok(locale_records)
 }
 
-fn set_null_locales(records: Vec<Record>) -> Result<Vec<Record>, Box<dyn Error>> {
-    let mut curr_locale = String::new();
+fn has_country(record: &Record) -> bool {
+    record.location.as_ref().is_some_and(|x| x.country.is_some())
+}
+
+/// The index of the known record a gap at `index` borrows its location from, if any.
+fn fill_source(records: &[Record], index: usize, strategy: FillStrategy) -> Option<usize> {
+    let previous = records[..index].iter().rposition(has_country);
+    let next = records[index + 1..].iter().position(has_country).map(|x| x + index + 1);
+    let distance = |other: usize| (records[other].local_datetime() - records[index].local_datetime()).num_seconds().abs();
+
+    match strategy {
+        FillStrategy::Forward => previous.or(next),
+        FillStrategy::Backward => next.or(previous),
+        FillStrategy::Nearest => match (previous, next) {
+            (Some(before), Some(after)) if distance(after) < distance(before) => Some(after),
+            (before, after) => before.or(after),
+        },
+        FillStrategy::LeaveUnknown => None,
+    }
+}
+
+/// Fills records with no country from a neighbour. A record with a partial location only takes the
+/// country; a record with none takes the whole location. Either way the provenance is marked as
+/// inherited from the neighbour's line. Anything left over is explicitly unknown.
+fn set_null_locales(records: Vec<Record>, strategy: FillStrategy) -> Result<Vec<Record>, ShipError> {
+    let sources: Vec<Option<(Location, Option<usize>)>> = (0..records.len())
+        .map(|index| match has_country(&records[index]) {
+            true => None,
+            false => fill_source(&records, index, strategy)
+                .and_then(|x| records[x].location.clone().map(|location| (location, records[x].line))),
+        })
+        .collect();
     let mut ammended_locales = Vec::new();
 
-    for mut rec in records {
-        match rec.locale {
-            Some(..) => {
-                curr_locale = rec.locale.as_ref().unwrap().to_owned();
-                ammended_locales.push(rec.to_owned());
+    for (mut rec, source) in records.into_iter().zip(sources) {
+        let inherited = |source: &Location, line: Option<usize>| source.provenance.as_ref().map(|x| x.inherit(line));
+
+        match (rec.location.as_mut(), source) {
+            (Some(location), Some((source, line))) => {
+                location.provenance = inherited(&source, line);
+                location.country = source.country;
             },
-            None => {
-                rec.set_locale(Some(curr_locale.to_owned()));
-                ammended_locales.push(rec);
-            }
+            (Some(location), None) if location.country.is_none() => location.provenance = Some(Provenance::unknown()),
+            (Some(..), None) => {},
+            (None, Some((source, line))) => rec.set_location(Some(Location { provenance: inherited(&source, line), ..source })),
+            (None, None) => rec.set_location(Some(Location { provenance: Some(Provenance::unknown()), ..Location::unknown() })),
         }
+        ammended_locales.push(rec);
     }
 
     Ok(ammended_locales)
 }
 
-pub fn data_builder(raw_data: Vec<String>) -> Result<Vec<Record>, Box<dyn Error>> {
-    let raw_records = parse_raw_records(raw_data).unwrap();
-    let base_locales = set_locales(raw_records).unwrap();
-    let filled_locales = set_null_locales(base_locales).unwrap();
+fn set_kinds(records: Vec<Record>, classifier: &Classifier) -> Result<Vec<Record>, ShipError> {
+    let mut kind_records = Vec::new();
+
+    for mut rec in records {
+        if rec.kind == EventKind::Unknown {
+            rec.set_kind(classifier.classify(&rec.status));
+        }
+        kind_records.push(rec);
+    }
+
+    Ok(kind_records)
+}
+
+fn set_offsets(records: Vec<Record>, zone: Option<&LocalZone>) -> Result<Vec<Record>, ShipError> {
+    let mut offset_records = Vec::new();
+
+    for mut rec in records {
+        if !rec.explicit_offset {
+            let offset = match zone {
+                Some(zone) => zone.offset_at(&rec.local_datetime()),
+                None => rec.location.as_ref()
+                    .and_then(|location| location.country.as_ref())
+                    .and_then(|country| offset_for_country(country, &rec.local_datetime()))
+                    .unwrap_or(Utc.fix()),
+            };
+            rec.set_offset(offset, false);
+        }
+        offset_records.push(rec);
+    }
+
+    offset_records.sort_by_key(|x| x.datetime);
 
-    Ok(filled_locales)
+    Ok(offset_records)
+}
+
+pub fn data_builder(raw_data: RawData) -> Result<Vec<Record>, ShipError> {
+    data_builder_with(raw_data, &BuildOptions::default())
+}
+
+/// Builds records strictly: the first line that can't be parsed fails the whole build.
+pub fn data_builder_with(raw_data: RawData, options: &BuildOptions) -> Result<Vec<Record>, ShipError> {
+    data_builder_with_diagnostics(raw_data, options, &mut Diagnostics::new(true))
+}
+
+fn parse_tracking(status: &str, pattern: &Regex) -> Option<String> {
+    let caps = pattern.captures(status)?;
+    caps.get(1).or_else(|| caps.get(0)).map(|x| x.as_str().to_string())
+}
+
+fn set_tracking(records: Vec<Record>, pattern: &Option<Regex>) -> Result<Vec<Record>, ShipError> {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return Ok(records),
+    };
+    let mut tracked_records = Vec::new();
+
+    for mut rec in records {
+        if rec.tracking.is_none() {
+            rec.set_tracking(parse_tracking(&rec.status, pattern));
+        }
+        tracked_records.push(rec);
+    }
+
+    Ok(tracked_records)
+}
+
+fn parse_raw_data(raw_data: RawData, options: &BuildOptions, diagnostics: &mut Diagnostics) -> Result<Vec<Record>, ShipError> {
+    let raw_records = match raw_data {
+        RawData::Lines(lines) if lines.is_empty() => Vec::new(),
+        RawData::Lines(lines) => {
+            let parser = match &options.time_format {
+                Some(parser) => parser.clone(),
+                None => {
+                    let text: Vec<String> = lines.iter().map(|(_, line)| line.clone()).collect();
+                    TimestampParser::detect(&text).ok_or(ShipError::NoTimestamps)?
+                },
+            };
+            parse_raw_records(lines, &parser, diagnostics)?
+        },
+        RawData::Records(mut records) => {
+            records.sort_by_key(|x| x.local_datetime());
+            records
+        }
+    };
+
+    set_tracking(raw_records, &options.tracking)
+}
+
+fn complete_records(raw_records: Vec<Record>, options: &BuildOptions) -> Result<Vec<Record>, ShipError> {
+    let base_locales = set_locales(raw_records, options)?;
+    let filled_locales = set_null_locales(base_locales, options.fill)?;
+    let offset_records = set_offsets(filled_locales, options.timezone.as_ref())?;
+    let kind_records = set_kinds(offset_records, &options.classifier)?;
+
+    Ok(kind_records)
+}
+
+pub fn data_builder_with_diagnostics(raw_data: RawData, options: &BuildOptions, diagnostics: &mut Diagnostics) -> Result<Vec<Record>, ShipError> {
+    let raw_records = parse_raw_data(raw_data, options, diagnostics)?;
+    complete_records(raw_records, options)
+}
+
+/// Splits the input by tracking number before locations are filled in, so one shipment's last
+/// known location never carries over into another. Shipments are in order of first appearance,
+/// with any untracked records kept together as one more shipment.
+pub fn shipment_builder(raw_data: RawData, options: &BuildOptions, diagnostics: &mut Diagnostics) -> Result<Vec<Shipment>, ShipError> {
+    let raw_records = parse_raw_data(raw_data, options, diagnostics)?;
+    let keys: Vec<Option<String>> = raw_records.iter().map(|x| x.tracking.clone()).unique().collect();
+    let mut shipments = Vec::new();
+
+    for key in keys {
+        let records = raw_records.iter()
+            .filter(|x| x.tracking == key)
+            .cloned()
+            .collect();
+        shipments.push(Shipment::new(key, complete_records(records, options)?));
+    }
+
+    Ok(shipments)
 }
 
 
@@ -105,52 +503,233 @@ mod tests {
 
     use super::*;
 
+    use celes::Country;
+    use chrono::FixedOffset;
+
+    use crate::fixtures::record_at;
+    use crate::models::event_kind::EventKind;
+    use crate::models::location::{LocationLevel, MatchRule};
+    use crate::timestamp::TimestampFormat;
+
     #[test]
     fn create_datetime() {
         assert!(parse_naive_datetime("2017-01-23 16:02:24").is_ok())
     }
 
+    #[test]
+    fn location_field() {
+        let options = BuildOptions::default();
+        assert_eq!(parse_location_field("DE", &options).unwrap().country, Some(Country::germany()));
+        assert_eq!(parse_location_field("CINCINNATI HUB, OH-USA", &options).unwrap().region, Some("OH".to_string()));
+        assert_eq!(parse_location_field(" ", &options), None);
+
+        let location = parse_location_field("Miami, FL", &options).unwrap();
+        assert_eq!(location.city, Some("Miami".to_string()));
+        assert_eq!(location.name(LocationLevel::Region), Some("FL, US".to_string()));
+        assert_eq!(parse_location_field("Miami FL, USA", &options).unwrap().city, Some("Miami".to_string()));
+    }
+
     #[test]
     fn get_raw_locale() {
-        assert!(parse_raw_locale("Package moved to customs office; Maimi, US").is_some())
+        assert!(parse_raw_locale("Package moved to customs office; Maimi, US", &CountryResolver::default(), &Gazetteer::default()).is_some())
+    }
+
+    #[test]
+    fn resolve_aliases_and_subdivisions() {
+        let (resolver, gazetteer) = (CountryResolver::default(), Gazetteer::default());
+        let resolve = |x: &str| parse_raw_locale(x, &resolver, &gazetteer).map(|(x, _)| (x.alpha2(), x.region, x.provenance.map(|x| x.rule)));
+
+        assert_eq!(resolve("Arrived at hub; Toronto ON"), Some((Some("CA"), Some("ON".to_string()), Some(MatchRule::Subdivision))));
+        assert_eq!(resolve("Departed; LONDON, UK"), Some((Some("GB"), None, Some(MatchRule::Alias))));
+        assert_eq!(resolve("Zugestellt in Berlin, Deutschland"), Some((Some("DE"), None, Some(MatchRule::Alias))));
+        assert_eq!(resolve("Arrived at PORT MORESBY - PAPUA NEW GUINEA").unwrap().0, Some("PG"));
+        assert_eq!(resolve("Departed Facility in CINCINNATI HUB,OH-USA").unwrap().2, Some(MatchRule::IsoAlpha3));
+        assert_eq!(resolve("Label created, handed over to"), None);
+    }
+
+    #[test]
+    fn states_after_a_place() {
+        let (resolver, gazetteer) = (CountryResolver::default(), Gazetteer::default());
+        let resolve = |x: &str| parse_raw_locale(x, &resolver, &gazetteer).map(|(x, _)| (x.alpha2(), x.region));
+        let state = |x: &str| Some((Some("US"), Some(x.to_string())));
+
+        assert_eq!(resolve("Delivered; Boston, MA"), state("MA"));
+        assert_eq!(resolve("Chicago, IL"), state("IL"));
+        assert_eq!(resolve("Departed from ATLANTA GA"), state("GA"));
+        assert_eq!(resolve("Arrived at facility; Atlanta, Georgia"), state("GA"));
+        assert_eq!(resolve("Delivered to ME"), state("ME"));
+        assert_eq!(resolve("Arrived at hub; Tbilisi, Georgia"), Some((Some("GE"), None)));
+        assert_eq!(resolve("Arrived at Sort Facility BERLIN - DE"), Some((Some("DE"), None)));
+        assert_eq!(resolve("Customs clearance - IL"), Some((Some("IL"), None)));
+    }
+
+    #[test]
+    fn get_place_parts() {
+        let location = parse_raw_locale("Departed Facility in CINCINNATI HUB,OH-USA", &CountryResolver::default(), &Gazetteer::default()).unwrap().0;
+        assert_eq!(location.facility, Some("CINCINNATI HUB".to_string()));
+        assert_eq!(location.city, Some("CINCINNATI".to_string()));
+        assert_eq!(location.region, Some("OH".to_string()));
+        assert_eq!(location.alpha3(), Some("USA"));
+
+        let location = parse_raw_locale("Package moved to customs office; Maimi, US", &CountryResolver::default(), &Gazetteer::default()).unwrap().0;
+        assert_eq!(location.city, Some("Maimi".to_string()));
+        assert_eq!(location.facility, None);
+
+        let location = parse_raw_locale("Arrived at Sort Facility LEIPZIG 04435 - GERMANY", &CountryResolver::default(), &Gazetteer::default()).unwrap().0;
+        assert_eq!(location.city, Some("LEIPZIG".to_string()));
+        assert_eq!(location.postal_code, Some("04435".to_string()));
     }
 
     #[test]
     fn raw_records() {
         let str_recs = vec![
-            "2017-01-23, 16:02:24, Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-            "2017-01-23, 14:48:08, Processed at CINCINNATI HUB,OH-USA".to_string()
+            (2, "2017-01-23, 16:02:24, Departed Facility in CINCINNATI HUB,OH-USA".to_string()),
+            (3, "2017-01-23, 14:48:08, Processed at CINCINNATI HUB,OH-USA".to_string())
+        ];
+        assert!(parse_raw_records(str_recs, &TimestampParser::known(TimestampFormat::Iso), &mut Diagnostics::new(true)).is_ok())
+    }
+
+    #[test]
+    fn unrecognised_record() {
+        let str_recs = vec![(2, "Customs status updated;".to_string())];
+        let err = parse_raw_records(str_recs, &TimestampParser::known(TimestampFormat::Iso), &mut Diagnostics::new(true)).unwrap_err();
+        assert_eq!(err.line(), Some(2));
+    }
+
+    #[test]
+    fn skip_unrecognised_record() {
+        let str_recs = vec![
+            (2, "2017-01-23, 16:02:24, Departed Facility in CINCINNATI HUB,OH-USA".to_string()),
+            (3, "Customs status updated;".to_string()),
+            (4, "2017-01-24, 06:45:10,".to_string()),
+        ];
+        let mut diagnostics = Diagnostics::new(false);
+        let records = parse_raw_records(str_recs, &TimestampParser::known(TimestampFormat::Iso), &mut diagnostics).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(diagnostics.warnings.iter().map(|x| x.line()).collect::<Vec<_>>(), vec![Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn split_by_tracking() {
+        let lines = vec![
+            (2, "2017-01-20, 09:12:44, 1Z999AA1 Shipment picked up; NEW YORK, NY-USA".to_string()),
+            (3, "2017-01-20, 10:00:00, JD0142 Shipment picked up; BERLIN - GERMANY".to_string()),
+            (4, "2017-01-21, 08:00:00, 1Z999AA1 Customs status updated;".to_string()),
+            (5, "2017-01-21, 09:00:00, JD0142 Out for delivery".to_string()),
+        ];
+        let options = BuildOptions { tracking: Some(Regex::new(r"\b(1Z\w+|JD\d+)\b").unwrap()), ..BuildOptions::default() };
+        let shipments = shipment_builder(RawData::Lines(lines), &options, &mut Diagnostics::new(true)).unwrap();
+
+        assert_eq!(shipments.len(), 2);
+        assert_eq!(shipments[0].key(), "1Z999AA1");
+        assert_eq!(shipments[0].records[1].location.as_ref().unwrap().alpha2(), Some("US"));
+        assert_eq!(shipments[1].records[1].location.as_ref().unwrap().alpha2(), Some("DE"));
+    }
+
+    #[test]
+    fn offsets_from_locale() {
+        let mut explicit = record_at("2017-01-24 06:00:00 -0500", "Departed Facility in CINCINNATI HUB,OH-USA", Some(Location::from_country(Country::germany())));
+        explicit.explicit_offset = true;
+        let recs = vec![
+            record_at("2017-01-24 06:45:10 +0000", "Arrived at Sort Facility LEIPZIG - GERMANY", Some(Location::from_country(Country::germany()))),
+            explicit,
+        ];
+        let new_recs = set_offsets(recs.clone(), None).unwrap();
+        assert_eq!(new_recs[0].utc_datetime().to_string(), "2017-01-24 05:45:10");
+        assert_eq!(new_recs[1].datetime.offset(), &FixedOffset::west_opt(5 * 3600).unwrap());
+
+        let zoned = set_offsets(recs, LocalZone::new("UTC").as_ref()).unwrap();
+        assert_eq!(zoned[0].utc_datetime().to_string(), "2017-01-24 06:45:10");
+        assert_eq!(zoned[1].datetime.offset(), &FixedOffset::west_opt(5 * 3600).unwrap());
+    }
+
+    #[test]
+    fn classify_records() {
+        let mut held = record_at("2017-01-24 18:10:36 +0000", "Customs status updated;", None);
+        held.set_kind(EventKind::CustomsHold);
+        let recs = vec![
+            record_at("2017-01-24 06:45:10 +0000", "Arrived at Sort Facility LEIPZIG - GERMANY", None),
+            held,
+        ];
+        let new_recs = set_kinds(recs, &Classifier::default()).unwrap();
+        assert_eq!(new_recs[0].kind, EventKind::Arrival);
+        assert_eq!(new_recs[1].kind, EventKind::CustomsHold);
+    }
+
+    #[test]
+    fn status_around_timestamp() {
+        let str_recs = vec![
+            "Departed Facility in CINCINNATI HUB,OH-USA, 23 Jan 2017 4:02 PM".to_string(),
+            "Customs status updated; 24 Jan 2017 6:10 PM".to_string(),
         ];
-        assert!(parse_raw_records(str_recs).is_ok())
+        let parser = TimestampParser::detect(&str_recs).unwrap();
+        let numbered = str_recs.into_iter().enumerate().collect();
+        let records = parse_raw_records(numbered, &parser, &mut Diagnostics::new(true)).unwrap();
+        assert_eq!(records[0].status, "Departed Facility in CINCINNATI HUB,OH-USA");
+        assert_eq!(records[1].status, "Customs status updated");
     }
 
     #[test]
     fn set_empty_locale() {
         let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: None
-            }
+            record_at("2017-01-23 16:02:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", None)
+        ];
+        assert!(set_locales(recs, &BuildOptions::default()).is_ok())
+    }
+
+    #[test]
+    fn locate_hub_without_country() {
+        let recs = vec![
+            record_at("2017-01-23 16:02:24 +0000", "Processed at CVG GATEWAY", None)
+        ];
+        let new_recs = set_locales(recs, &BuildOptions::default()).unwrap();
+        let location = new_recs[0].location.as_ref().unwrap();
+
+        assert_eq!(location.alpha2(), Some("US"));
+        assert_eq!(location.facility, Some("CVG GATEWAY".to_string()));
+        assert_eq!(location.city, Some("CINCINNATI".to_string()));
+        assert_eq!(location.provenance.as_ref().map(|x| x.rule), Some(MatchRule::Iata));
+        assert!(location.coordinates.is_some());
+    }
+
+    #[test]
+    fn keep_explicit_locale() {
+        let recs = vec![
+            record_at("2017-01-23 16:02:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", Some(Location::from_country(Country::canada())))
         ];
-        assert!(set_locales(recs).is_ok())
+        let new_recs = set_locales(recs, &BuildOptions::default()).unwrap();
+        assert_eq!(new_recs[0].location, Some(Location::from_country(Country::canada())))
     }
 
     #[test]
     fn set_nulls() {
         let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-24 18:10:36", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Customs status updated;".to_string(),
-                locale: None,
-            },
+            record_at("2017-01-23 16:02:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", Some(Location::from_country(Country::the_united_states_of_america()))),
+            record_at("2017-01-24 18:10:36 +0000", "Customs status updated;", None),
+        ];
+        let new_recs = set_null_locales(recs, FillStrategy::Forward).unwrap();
+        assert_eq!(new_recs[0].location, new_recs[1].location)
+    }
+
+    #[test]
+    fn fill_strategies() {
+        let record = |datetime: &str, country: Option<Country>| record_at(datetime, "Processed", country.map(Location::from_country));
+        let recs = vec![
+            record("2017-01-20 08:00:00 +0000", None),
+            record("2017-01-20 10:00:00 +0000", Some(Country::the_united_states_of_america())),
+            record("2017-01-21 09:00:00 +0000", None),
+            record("2017-01-21 10:00:00 +0000", Some(Country::germany())),
         ];
-        let new_recs = set_null_locales(recs).unwrap();
-        assert_eq!(new_recs[0].locale, new_recs[1].locale)
+        let countries = |strategy| set_null_locales(recs.clone(), strategy).unwrap().iter()
+            .map(|x| x.location.as_ref().and_then(|x| x.alpha2()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(countries(FillStrategy::Forward), vec![Some("US"), Some("US"), Some("US"), Some("DE")]);
+        assert_eq!(countries(FillStrategy::Backward), vec![Some("US"), Some("US"), Some("DE"), Some("DE")]);
+        assert_eq!(countries(FillStrategy::Nearest), vec![Some("US"), Some("US"), Some("DE"), Some("DE")]);
+        assert_eq!(countries(FillStrategy::LeaveUnknown), vec![None, Some("US"), None, Some("DE")]);
+
+        let unknown = set_null_locales(recs.clone(), FillStrategy::LeaveUnknown).unwrap();
+        assert!(unknown[0].location.as_ref().unwrap().is_unknown());
     }
 }
@@ -1,75 +1,162 @@
-use std::error::Error;
-use chrono::NaiveDateTime;
+use std::cmp::Reverse;
+use std::ops::Sub;
 use itertools::Itertools;
 
-use crate::models::{record::Record, transfer::Transfer};
+use crate::calendar::BusinessCalendar;
+use crate::error::ShipError;
+use crate::models::{event_kind::EventKind, location::LocationLevel, record::Record, stay::Dwell, transfer::Transfer};
+use crate::segment::segment_stays_with;
 
-fn calc_transfers(records: &[Record]) -> Result<Vec<Transfer>, Box<dyn Error>> {
+/// Every consecutive leg, in time order.
+pub fn calc_transfers(records: &[Record]) -> Result<Vec<Transfer>, ShipError> {
+    calc_transfers_with(records, None)
+}
+
+/// Every consecutive leg, with its business time as well when a calendar is given.
+pub fn calc_transfers_with(records: &[Record], calendar: Option<&BusinessCalendar>) -> Result<Vec<Transfer>, ShipError> {
     let transfers: Vec<Transfer> = records.windows(2)
         .map(|pair| {
-            let mins = diff_naive_datetime(&pair[0].datetime, &pair[1].datetime);
-            let transfer = Transfer::new(pair[0].clone(), pair[1].clone(), mins).unwrap();
-            transfer
+            let minutes = diff_datetime(&pair[0].datetime, &pair[1].datetime);
+            let business_minutes = calendar.map(|x| calc_business_time(x, &pair[0], &pair[1]));
+            Transfer { from: pair[0].clone(), to: pair[1].clone(), minutes, business_minutes }
         })
         .collect();
 
     Ok(transfers)
 }
 
-fn longest_transfer(transfers: Vec<Transfer>) -> Transfer {
-    transfers.into_iter().max_by_key(|x| x.minutes).unwrap()
+/// Working minutes from one scan to the next, with the holidays of the country the first scan is in.
+pub fn calc_business_time(calendar: &BusinessCalendar, from: &Record, to: &Record) -> i64 {
+    let country = from.location.as_ref().and_then(|x| x.country.as_ref());
+    calendar.business_minutes(&from.datetime, &to.datetime, country)
+}
+
+/// Working minutes over a run of scans, each step counted in the country it starts in.
+fn calc_business_span(records: &[Record], calendar: &BusinessCalendar) -> i64 {
+    records.windows(2)
+        .map(|pair| calc_business_time(calendar, &pair[0], &pair[1]))
+        .sum()
+}
+
+fn longest_transfer(transfers: Vec<Transfer>) -> Option<Transfer> {
+    transfers.into_iter().max_by_key(|x| x.minutes)
+}
+
+/// Longest first. Legs of equal length keep their order in time.
+pub fn sort_transfers_by_duration(mut transfers: Vec<Transfer>) -> Vec<Transfer> {
+    transfers.sort_by_key(|x| Reverse(x.minutes));
+    transfers
+}
+
+pub fn calc_longest_transfers(records: &[Record], count: usize) -> Result<Vec<Transfer>, ShipError> {
+    calc_longest_transfers_with(records, count, None)
+}
+
+pub fn calc_longest_transfers_with(records: &[Record], count: usize, calendar: Option<&BusinessCalendar>) -> Result<Vec<Transfer>, ShipError> {
+    let mut transfers = sort_transfers_by_duration(calc_transfers_with(records, calendar)?);
+    transfers.truncate(count);
+    Ok(transfers)
 }
 
-fn diff_naive_datetime(start: &NaiveDateTime, end: &NaiveDateTime) -> i64 {
+/// Elapsed minutes between two instants. Offset-aware timestamps give true elapsed time across zones.
+pub(crate) fn diff_datetime<T>(start: &T, end: &T) -> i64
+where
+    T: Copy + Sub<T, Output = chrono::Duration>,
+{
     let diff: chrono::Duration = *end - *start;
     diff.num_minutes()
 }
 
-fn generate_locales_list(records: &[Record]) -> Vec<String> {
+pub fn location_name(record: &Record, level: LocationLevel) -> Option<String> {
+    record.location.as_ref().and_then(|x| x.name(level))
+}
+
+fn generate_locales_list(records: &[Record], level: LocationLevel) -> Vec<String> {
     let mut locales = Vec::new();
 
     for rec in records {
-        locales.push(rec.locale.clone().unwrap())
+        if let Some(name) = location_name(rec, level) {
+            locales.push(name)
+        }
     }
 
     locales.into_iter().unique().collect()
 }
 
-fn filter_layover_time(records: &[Record], search_locale: &str) -> Result<Transfer, String> {
-    let filter_records: Vec<Record> = records.iter().cloned()
-        .filter(|x| x.locale.as_ref().unwrap() == search_locale)
-        .collect();
-
-    if filter_records.is_empty() {
-        return Err("No records found for the given locale.".to_owned());
+pub fn calc_total_shipment_time(records: &[Record]) -> Result<i64, ShipError> {
+    match (records.first(), records.last()) {
+        (Some(first), Some(last)) => Ok(diff_datetime(&first.datetime, &last.datetime)),
+        _ => Err(ShipError::NoRecords),
     }
+}
 
-    let from = filter_records[0].clone();
-    let to = filter_records.last().unwrap().to_owned();
-    let mins = diff_naive_datetime(&from.datetime, &to.datetime);
-    let transfer = Transfer::new(from, to, mins).unwrap();
+pub fn calc_total_business_time(records: &[Record], calendar: &BusinessCalendar) -> Result<i64, ShipError> {
+    match records.is_empty() {
+        true => Err(ShipError::NoRecords),
+        false => Ok(calc_business_span(records, calendar)),
+    }
+}
 
-    Ok(transfer)
+pub fn calc_layover_times(records: &[Record]) -> Result<Vec<Dwell>, ShipError> {
+    calc_layover_times_by(records, LocationLevel::Country)
 }
 
-pub fn calc_total_shipment_time(records: &[Record]) -> Result<i64, Box<dyn Error>> {
-    Ok(diff_naive_datetime(&records[0].datetime, &records.last().unwrap().datetime))
+/// Dwell per location in order of first visit, summing only the time spent there on each visit.
+pub fn calc_layover_times_by(records: &[Record], level: LocationLevel) -> Result<Vec<Dwell>, ShipError> {
+    calc_layover_times_with(records, level, None)
 }
 
-pub fn calc_layover_times(records: &[Record]) -> Result<Vec<Transfer>, Box<dyn Error>> {
-    let search_locales = generate_locales_list(records);
+pub fn calc_layover_times_with(records: &[Record], level: LocationLevel, calendar: Option<&BusinessCalendar>) -> Result<Vec<Dwell>, ShipError> {
+    let stays = segment_stays_with(records, level, calendar);
     let mut layovers = Vec::new();
 
-    for local in search_locales {
-        let tranfer = filter_layover_time(records, &local).unwrap();
-        layovers.push(tranfer);
+    for name in generate_locales_list(records, level) {
+        let visits: Vec<_> = stays.iter().filter(|x| x.name == name).collect();
+        let minutes = visits.iter().map(|x| x.minutes).sum();
+        let business_minutes = calendar.map(|_| visits.iter().filter_map(|x| x.business_minutes).sum());
+        layovers.push(Dwell { name, visits: visits.len(), minutes, business_minutes });
     }
 
     Ok(layovers)
 }
 
-pub fn calc_longest_delay(records: &[Record]) -> Result<Transfer, Box<dyn Error>> {
-    let transfers = calc_transfers(records).unwrap();
+/// Milestones shown in every report, as (label, from kind, to kind).
+pub const MILESTONES: [(&str, EventKind, EventKind); 3] = [
+    ("Pickup to first departure", EventKind::Pickup, EventKind::Departure),
+    ("Pickup to delivery", EventKind::Pickup, EventKind::Delivered),
+    ("Out for delivery to delivery", EventKind::OutForDelivery, EventKind::Delivered),
+];
+
+pub fn find_event(records: &[Record], kind: EventKind) -> Option<&Record> {
+    records.iter().find(|x| x.kind == kind)
+}
+
+/// Minutes from the first event of one kind to the first event of another kind that follows it,
+/// e.g. from pickup to the first departure.
+pub fn calc_time_between(records: &[Record], from: EventKind, to: EventKind) -> Option<i64> {
+    let (start, end) = find_between(records, from, to)?;
+    Some(diff_datetime(&records[start].datetime, &records[end].datetime))
+}
+
+pub fn calc_business_time_between(records: &[Record], from: EventKind, to: EventKind, calendar: &BusinessCalendar) -> Option<i64> {
+    let (start, end) = find_between(records, from, to)?;
+    Some(calc_business_span(&records[start..=end], calendar))
+}
+
+fn find_between(records: &[Record], from: EventKind, to: EventKind) -> Option<(usize, usize)> {
+    let start = records.iter().position(|x| x.kind == from)?;
+    let end = records[start..].iter().position(|x| x.kind == to)?;
+
+    Some((start, start + end))
+}
+
+/// `None` when there are fewer than two records, as there is no step to measure yet.
+pub fn calc_longest_delay(records: &[Record]) -> Result<Option<Transfer>, ShipError> {
+    calc_longest_delay_with(records, None)
+}
+
+pub fn calc_longest_delay_with(records: &[Record], calendar: Option<&BusinessCalendar>) -> Result<Option<Transfer>, ShipError> {
+    let transfers = calc_transfers_with(records, calendar)?;
     Ok(longest_transfer(transfers))
 }
 
@@ -81,86 +168,127 @@ mod test {
     use super::*;
 
     use celes::Country;
-    use chrono::NaiveDateTime;
+    use chrono::{DateTime, NaiveDateTime};
+
+    use crate::fixtures::record_at;
+    use crate::models::event_kind::EventKind;
+    use crate::models::location::Location;
 
     #[test]
-    fn test_diff_naive_datetime() {
+    fn test_diff_datetime() {
         let dt_1 = NaiveDateTime::parse_from_str("2017-01-22 15:23:58", "%Y-%m-%d %H:%M:%S").unwrap();
         let dt_2 = NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap();
-        let sum = diff_naive_datetime(&dt_1, &dt_2);
+        let sum = diff_datetime(&dt_1, &dt_2);
         assert_eq!(sum, 1478);
     }
 
+    #[test]
+    fn test_diff_across_offsets() {
+        let dt_1 = DateTime::parse_from_str("2017-01-23 16:02:24 -0500", "%Y-%m-%d %H:%M:%S %z").unwrap();
+        let dt_2 = DateTime::parse_from_str("2017-01-24 06:45:10 +0100", "%Y-%m-%d %H:%M:%S %z").unwrap();
+        assert_eq!(diff_datetime(&dt_1, &dt_2), 522);
+    }
+
     #[test]
     fn test_transfers() {
         let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-24 18:10:36", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Customs status updated;".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
+            record_at("2017-01-23 16:02:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", Some(Location::from_country(Country::the_united_states_of_america()))),
+            record_at("2017-01-24 18:10:36 +0000", "Customs status updated;", Some(Location::from_country(Country::the_united_states_of_america()))),
         ];
         let transfers = calc_transfers(&recs);
         assert!(transfers.is_ok());
     }
 
+    #[test]
+    fn longest_transfers_first() {
+        let record = |datetime: &str| record_at(datetime, "Processed", None);
+        let recs = vec![
+            record("2017-01-20 10:00:00 +0000"),
+            record("2017-01-20 11:00:00 +0000"),
+            record("2017-01-20 14:00:00 +0000"),
+            record("2017-01-20 16:00:00 +0000"),
+        ];
+        let minutes = |legs: Vec<Transfer>| legs.iter().map(|x| x.minutes).collect::<Vec<_>>();
+        assert_eq!(minutes(calc_transfers(&recs).unwrap()), vec![60, 180, 120]);
+        assert_eq!(minutes(calc_longest_transfers(&recs, 2).unwrap()), vec![180, 120]);
+        assert_eq!(minutes(calc_longest_transfers(&recs, 10).unwrap()), vec![180, 120, 60]);
+    }
+
+    #[test]
+    fn time_between_kinds() {
+        let record = |datetime: &str, status: &str, kind: EventKind| {
+            let mut record = record_at(datetime, status, None);
+            record.set_kind(kind);
+            record
+        };
+        let recs = vec![
+            record("2017-01-23 16:02:24 +0000", "Shipment picked up", EventKind::Pickup),
+            record("2017-01-23 18:10:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", EventKind::Departure),
+        ];
+        assert_eq!(calc_time_between(&recs, EventKind::Pickup, EventKind::Departure), Some(128));
+        assert_eq!(calc_time_between(&recs, EventKind::Departure, EventKind::Pickup), None);
+        assert!(find_event(&recs, EventKind::Delivered).is_none());
+    }
+
     #[test]
     fn gen_locales_vec() {
         let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-24 18:10:36", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Customs status updated;".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
+            record_at("2017-01-23 16:02:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", Some(Location::from_country(Country::the_united_states_of_america()))),
+            record_at("2017-01-24 18:10:36 +0000", "Customs status updated;", Some(Location::from_country(Country::the_united_states_of_america()))),
         ];
-        let locales = generate_locales_list(&recs);
-        assert_eq!(locales[0], Country::the_united_states_of_america().to_string())
+        let locales = generate_locales_list(&recs, LocationLevel::Country);
+        assert_eq!(locales[0], Country::the_united_states_of_america().long_name)
     }
 
     #[test]
-    fn filter_for_good_locale() {
+    fn layover_excludes_other_countries() {
+        let record = |datetime: &str, country: Country| record_at(datetime, "Processed", Some(Location::from_country(country)));
         let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-24 18:10:36", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Customs status updated;".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
+            record("2017-01-20 10:00:00 +0000", Country::the_united_states_of_america()),
+            record("2017-01-20 12:00:00 +0000", Country::the_united_states_of_america()),
+            record("2017-01-21 09:00:00 +0000", Country::canada()),
+            record("2017-01-23 08:00:00 +0000", Country::the_united_states_of_america()),
+            record("2017-01-23 09:15:00 +0000", Country::the_united_states_of_america()),
         ];
-        let search_locale = Country::the_united_states_of_america().to_string();
-        assert!(filter_layover_time(&recs, &search_locale).is_ok())
+        let layovers = calc_layover_times(&recs).unwrap();
+        assert_eq!(layovers[0].name, Country::the_united_states_of_america().long_name);
+        assert_eq!(layovers[0].visits, 2);
+        assert_eq!(layovers[0].minutes, 195);
+        assert_eq!(layovers[1].minutes, 0);
     }
 
     #[test]
-    #[should_panic]
-    fn filter_for_bad_locale() {
+    fn business_time_alongside_elapsed() {
+        let record = |datetime: &str, kind: EventKind| {
+            let mut record = record_at(datetime, "Processed", Some(Location::from_country(Country::germany())));
+            record.set_kind(kind);
+            record
+        };
+        // Friday afternoon to Monday morning.
         let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-24 18:10:36", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Customs status updated;".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
+            record("2017-01-20 16:00:00 +0100", EventKind::Pickup),
+            record("2017-01-21 10:00:00 +0100", EventKind::Processed),
+            record("2017-01-23 08:00:00 +0100", EventKind::Delivered),
         ];
-        let search_locale = Country::canada().to_string();
-        filter_layover_time(&recs, &search_locale).unwrap();
+        let calendar = BusinessCalendar::default();
+        let legs = calc_transfers_with(&recs, Some(&calendar)).unwrap();
+        assert_eq!(legs[0].minutes, 1080);
+        assert_eq!(legs[0].business_minutes, Some(480));
+        assert_eq!(legs[1].business_minutes, Some(480));
+        assert_eq!(calc_total_business_time(&recs, &calendar).unwrap(), 960);
+        assert_eq!(calc_business_time_between(&recs, EventKind::Pickup, EventKind::Delivered, &calendar), Some(960));
+        assert_eq!(calc_layover_times_with(&recs, LocationLevel::Country, Some(&calendar)).unwrap()[0].business_minutes, Some(960));
+        assert_eq!(calc_transfers(&recs).unwrap()[0].business_minutes, None);
+    }
+
+    #[test]
+    fn single_record_has_no_delay() {
+        let mut created = record_at("2017-01-20 10:00:00 +0000", "Shipment information received", None);
+        created.set_kind(EventKind::Created);
+        let recs = vec![created];
+        assert_eq!(calc_longest_delay(&recs).unwrap(), None);
+        assert_eq!(calc_longest_delay(&[]).unwrap(), None);
+        assert_eq!(calc_total_shipment_time(&recs).unwrap(), 0);
+        assert!(calc_transfers(&recs).unwrap().is_empty());
     }
 }
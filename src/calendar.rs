@@ -0,0 +1,166 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+use celes::Country;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, Weekday};
+use serde::Deserialize;
+
+use crate::error::ShipError;
+
+#[derive(Debug, Deserialize)]
+struct HoursConfig {
+    start: String,
+    end: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarConfig {
+    weekend: Option<Vec<String>>,
+    hours: Option<HoursConfig>,
+    #[serde(default)]
+    holidays: HashMap<String, Vec<String>>,
+}
+
+
+/// Which local minutes count as working time. Days on the weekend or on a holiday of the country the
+/// shipment is in don't count at all; on working days only the working hours do, when they are set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusinessCalendar {
+    pub weekend: Vec<Weekday>,
+    /// Holidays by alpha-2 country code. Dates under `*` apply in every country.
+    pub holidays: HashMap<String, HashSet<NaiveDate>>,
+    pub hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Default for BusinessCalendar {
+    fn default() -> BusinessCalendar {
+        BusinessCalendar { weekend: vec![Weekday::Sat, Weekday::Sun], holidays: HashMap::new(), hours: None }
+    }
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, ShipError> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| ShipError::Config(format!("Calendar hours expect HH:MM, got '{}'", value)))
+}
+
+fn parse_country_code(name: &str) -> Result<String, ShipError> {
+    match name {
+        "*" => Ok(name.to_string()),
+        _ => Country::from_str(name.trim())
+            .map(|x| x.alpha2.to_string())
+            .map_err(|_| ShipError::Config(format!("Unknown calendar country: '{}'", name))),
+    }
+}
+
+impl BusinessCalendar {
+    /// Loads a calendar from a JSON object such as `{ "weekend": ["sat", "sun"], "hours": { "start": "09:00",
+    /// "end": "17:00" }, "holidays": { "US": ["2017-01-16"], "*": ["2017-12-25"] } }`. Every key is optional.
+    pub fn from_file(path: &Path) -> Result<BusinessCalendar, ShipError> {
+        let file = File::open(path).map_err(|err| ShipError::from(err).in_file(&path.to_string_lossy()))?;
+        let config: CalendarConfig = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| ShipError::Config(format!("Invalid calendar file: {}", err)))?;
+        let mut calendar = BusinessCalendar::default();
+
+        if let Some(days) = config.weekend {
+            calendar.weekend = days.iter()
+                .map(|x| Weekday::from_str(x).map_err(|_| ShipError::Config(format!("Unknown weekday: '{}'", x))))
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        if let Some(hours) = config.hours {
+            let (start, end) = (parse_time(&hours.start)?, parse_time(&hours.end)?);
+            if end <= start {
+                return Err(ShipError::Config("Calendar hours must end after they start.".to_string()));
+            }
+            calendar.hours = Some((start, end));
+        }
+        for (country, dates) in config.holidays {
+            let dates = dates.iter()
+                .map(|x| NaiveDate::parse_from_str(x, "%Y-%m-%d").map_err(|_| ShipError::Config(format!("Holidays expect YYYY-MM-DD, got '{}'", x))))
+                .collect::<Result<HashSet<_>, _>>()?;
+            calendar.holidays.entry(parse_country_code(&country)?).or_default().extend(dates);
+        }
+
+        Ok(calendar)
+    }
+
+    fn is_holiday(&self, day: NaiveDate, country: Option<&Country>) -> bool {
+        let listed = |code: &str| self.holidays.get(code).is_some_and(|x| x.contains(&day));
+        listed("*") || country.is_some_and(|x| listed(x.alpha2))
+    }
+
+    /// Working minutes in a full working day: the working hours, or the whole day when none are set.
+    pub fn day_minutes(&self) -> i64 {
+        match self.hours {
+            Some((start, end)) => (end - start).num_minutes(),
+            None => 24 * 60,
+        }
+    }
+
+    pub fn is_working_day(&self, day: NaiveDate, country: Option<&Country>) -> bool {
+        !self.weekend.contains(&day.weekday()) && !self.is_holiday(day, country)
+    }
+
+    /// Working minutes between two instants, counted on the local clock of `start` and with the holidays of `country`.
+    pub fn business_minutes(&self, start: &DateTime<FixedOffset>, end: &DateTime<FixedOffset>, country: Option<&Country>) -> i64 {
+        if end <= start {
+            return 0;
+        }
+
+        let local_start = start.naive_local();
+        let local_end = end.with_timezone(start.offset()).naive_local();
+        let mut seconds = 0;
+        let mut day = local_start.date();
+
+        while day <= local_end.date() {
+            if self.is_working_day(day, country) {
+                let (open, close) = match self.hours {
+                    Some((open, close)) => (day.and_time(open), day.and_time(close)),
+                    None => (day.and_time(NaiveTime::MIN), day.and_time(NaiveTime::MIN) + Duration::days(1)),
+                };
+                let (from, to) = (open.max(local_start), close.min(local_end));
+                if to > from {
+                    seconds += (to - from).num_seconds();
+                }
+            }
+            day = match day.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        seconds / 60
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn datetime(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S %z").unwrap()
+    }
+
+    #[test]
+    fn skip_weekends() {
+        let calendar = BusinessCalendar::default();
+        // Friday 18:00 to Monday 10:00: six hours on Friday, ten on Monday.
+        let minutes = calendar.business_minutes(&datetime("2017-01-20 18:00:00 -0500"), &datetime("2017-01-23 10:00:00 -0500"), None);
+        assert_eq!(minutes, 16 * 60);
+    }
+
+    #[test]
+    fn holidays_and_hours() {
+        let calendar = BusinessCalendar::from_file(Path::new("tests/data/config/calendar.json")).unwrap();
+        let us = Country::the_united_states_of_america();
+        let start = datetime("2017-01-13 16:00:00 -0500");
+        let end = datetime("2017-01-17 10:00:00 -0500");
+        // Friday 16:00-17:00, Monday is MLK day in the US, Tuesday 09:00-10:00.
+        assert_eq!(calendar.business_minutes(&start, &end, Some(&us)), 120);
+        assert_eq!(calendar.business_minutes(&start, &end, Some(&Country::germany())), 600);
+    }
+}
@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::ShipError;
+use crate::models::event_kind::EventKind;
+
+/// Built-in rules, tried in order. More specific kinds come first, so "Arrived at Delivery
+/// Facility" is an arrival and "order processed" a created label rather than a sort scan.
+const DEFAULT_RULES: &[(EventKind, &str)] = &[
+    (EventKind::DeliveryAttempt, r"delivery attempt|attempted delivery|unable to deliver|no one (home|available)|recipient not (home|available)"),
+    (EventKind::OutForDelivery, r"out for delivery|with delivery courier|on vehicle for delivery"),
+    (EventKind::Delivered, r"\bdelivered\b|delivery completed|signed for"),
+    (EventKind::CustomsHold, r"held (by|in|at) customs|customs (hold|held|delay)|clearance delay|awaiting clearance"),
+    (EventKind::CustomsCleared, r"clearance (processing )?complete|cleared customs|customs clear|released by customs"),
+    (EventKind::Customs, r"customs|clearance"),
+    (EventKind::Created, r"label created|shipment information received|electronic(ally)? (notification|submitted)|order processed"),
+    (EventKind::Pickup, r"picked up|pickup|pick-up|collected|accepted at"),
+    (EventKind::Departure, r"depart|\bleft\b|dispatched|in transit to"),
+    (EventKind::Arrival, r"arrived|arrival|received at"),
+    (EventKind::Processed, r"processed|sorted|scanned|sort facility"),
+    (EventKind::Exception, r"exception|damaged|returned|\blost\b|refused|delay"),
+];
+
+#[derive(Debug, Deserialize)]
+struct RuleConfig {
+    kind: EventKind,
+    pattern: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub kind: EventKind,
+    pub pattern: Regex,
+}
+
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    rules: Vec<Rule>,
+}
+
+impl Rule {
+    pub fn new(kind: EventKind, pattern: &str) -> Result<Rule, regex::Error> {
+        let pattern = Regex::new(&format!("(?i){}", pattern))?;
+        Ok(Rule { kind, pattern })
+    }
+}
+
+impl Default for Classifier {
+    fn default() -> Classifier {
+        let rules = DEFAULT_RULES.iter()
+            .map(|(kind, pattern)| Rule::new(*kind, pattern).unwrap())
+            .collect();
+
+        Classifier { rules }
+    }
+}
+
+impl Classifier {
+    /// Loads user rules from a JSON array of `{ "kind": "customs_hold", "pattern": "..." }` objects.
+    /// User rules are tried before the built-in ones.
+    pub fn from_file(path: &Path) -> Result<Classifier, ShipError> {
+        let file = File::open(path).map_err(|err| ShipError::from(err).in_file(&path.to_string_lossy()))?;
+        let configs: Vec<RuleConfig> = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| ShipError::Config(format!("Invalid rules file: {}", err)))?;
+        let mut classifier = Classifier::default();
+
+        for config in configs.into_iter().rev() {
+            let rule = Rule::new(config.kind, &config.pattern)
+                .map_err(|err| ShipError::Config(format!("Invalid pattern for {}: {}", config.kind.name(), err)))?;
+            classifier.prepend(rule);
+        }
+
+        Ok(classifier)
+    }
+
+    pub fn prepend(&mut self, rule: Rule) {
+        self.rules.insert(0, rule)
+    }
+
+    pub fn classify(&self, status: &str) -> EventKind {
+        self.rules.iter()
+            .find(|rule| rule.pattern.is_match(status))
+            .map(|rule| rule.kind)
+            .unwrap_or(EventKind::Unknown)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn default_rules() {
+        let classifier = Classifier::default();
+        assert_eq!(classifier.classify("Shipment picked up; NEW YORK, NY-USA"), EventKind::Pickup);
+        assert_eq!(classifier.classify("Departed Facility in CINCINNATI HUB,OH-USA"), EventKind::Departure);
+        assert_eq!(classifier.classify("Arrived at Delivery Facility in BERLIN - GERMANY"), EventKind::Arrival);
+        assert_eq!(classifier.classify("Customs status updated;"), EventKind::Customs);
+        assert_eq!(classifier.classify("Shipment delivered; BERLIN - GERMANY"), EventKind::Delivered);
+        assert_eq!(classifier.classify("Weather report"), EventKind::Unknown);
+    }
+
+    #[test]
+    fn user_rules_first() {
+        let mut classifier = Classifier::default();
+        classifier.prepend(Rule::new(EventKind::CustomsHold, "customs status updated").unwrap());
+        assert_eq!(classifier.classify("Customs status updated;"), EventKind::CustomsHold);
+    }
+
+    #[test]
+    fn rules_from_file() {
+        let classifier = Classifier::from_file(Path::new("tests/data/config/rules.json")).unwrap();
+        assert_eq!(classifier.classify("Customs status updated;"), EventKind::CustomsHold);
+        assert!(matches!(Classifier::from_file(Path::new("tests/data/config/sla.json")), Err(ShipError::Config(_))));
+    }
+}
@@ -0,0 +1,480 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+use regex::Regex;
+
+use crate::batch::{BatchOptions, BatchOutput};
+use crate::builder::{shipment_builder, BuildOptions, FillStrategy};
+use crate::calendar::BusinessCalendar;
+use crate::classifier::Classifier;
+use crate::error::{Diagnostics, ShipError};
+use crate::models::columns::ColumnMap;
+use crate::models::input::Input;
+use crate::models::location::LocationLevel;
+use crate::models::report::{LegOrder, OutputFormat, ReportOptions, Section};
+use crate::models::shipment::Shipment;
+use crate::reader::reader_with;
+use crate::resolver::CountryResolver;
+use crate::sla::SlaConfig;
+use crate::timestamp::TimestampParser;
+use crate::timezone::LocalZone;
+
+/// Reports transit, layover and leg times from carrier tracking exports (.txt, .csv or .json).
+#[derive(Debug, Parser)]
+#[command(name = "shiptracker", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write the shipment report, or one report per tracking number followed by a portfolio summary.
+    Report(ReportArgs),
+    /// Read and build an input, listing every problem found instead of writing a report.
+    Validate(ValidateArgs),
+    /// Rewrite an input as normalised JSON events or CSV rows.
+    Convert(ConvertArgs),
+    /// Summary and statistics across every shipment in an input.
+    Stats(StatsArgs),
+    /// Compare the reports of two inputs, shipment by shipment.
+    Diff(DiffArgs),
+    /// Show, line by line, how each record's location was derived.
+    Explain(ExplainArgs),
+    /// Check each shipment against service-level commitments. Exits with 6 on a breach.
+    Sla(SlaArgs),
+    /// Write a report for every file given, or every .txt, .csv and .json file in a directory.
+    Batch(BatchArgs),
+}
+
+/// How an input is read and built into records.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ReadArgs {
+    /// Input format, in place of the file extension. Required when reading stdin.
+    #[arg(long, value_name = "FORMAT", value_parser = ["txt", "csv", "json"])]
+    pub input_format: Option<String>,
+    /// Map CSV columns to fields by header or index, e.g. 'timestamp=Date,status=2'.
+    #[arg(long, value_name = "MAP", value_parser = parse_columns)]
+    pub columns: Option<ColumnMap>,
+    /// Timestamp format: iso, mdy, dmy, dotted, day-month, epoch or a chrono pattern.
+    #[arg(long, value_name = "FORMAT", value_parser = parse_time_format)]
+    pub time_format: Option<TimestampParser>,
+    /// Read timestamps that carry no offset in this zone (UTC, +02:00 or a country) rather than the scan's country.
+    #[arg(long, value_name = "ZONE", value_parser = parse_zone)]
+    pub timezone: Option<LocalZone>,
+    /// Pull a tracking number out of each status line, using the first capture group when there is one.
+    #[arg(long, value_name = "REGEX")]
+    pub tracking: Option<Regex>,
+    /// Event classification rules (JSON).
+    #[arg(long, value_name = "FILE")]
+    pub rules: Option<PathBuf>,
+    /// Country aliases (JSON), tried before the built-in dictionary.
+    #[arg(long, value_name = "FILE")]
+    pub aliases: Option<PathBuf>,
+    /// How scans with no recognisable country are filled in: forward, backward, nearest or unknown.
+    #[arg(long, value_name = "STRATEGY", default_value = "forward", value_parser = parse_fill)]
+    pub fill: FillStrategy,
+    /// Stop at the first line that can't be parsed instead of skipping it with a warning.
+    #[arg(long)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct InputArgs {
+    /// Input file, or '-' for stdin.
+    pub file: String,
+    #[command(flatten)]
+    pub read: ReadArgs,
+}
+
+/// How reports are computed.
+#[derive(Debug, Args)]
+pub struct ReportingArgs {
+    /// Group layovers and stays by facility, city, region or country.
+    #[arg(long, value_name = "LEVEL", default_value = "country", value_parser = parse_level)]
+    pub group_by: LocationLevel,
+    /// Order legs by time, or by duration with the longest first.
+    #[arg(long, value_name = "ORDER", default_value = "time", value_parser = parse_leg_order)]
+    pub sort_legs: LegOrder,
+    /// Show the N longest legs in place of the single longest delay.
+    #[arg(long, value_name = "N", value_parser = parse_count)]
+    pub top_legs: Option<usize>,
+    /// Report business time, without weekends, next to elapsed time.
+    #[arg(long)]
+    pub business_time: bool,
+    /// Business calendar (JSON) for business time. Implies --business-time.
+    #[arg(long, value_name = "FILE")]
+    pub calendar: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Output format: text, json, csv, markdown or html. Defaults to the output file's extension.
+    #[arg(short, long, value_name = "FORMAT", value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// Write to this file instead of stdout.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub reporting: ReportingArgs,
+    /// Sections to include, comma-separated: total, layovers, stays, milestones, legs, longest. All by default.
+    #[arg(long, value_name = "LIST", value_delimiter = ',', value_parser = parse_section)]
+    pub sections: Vec<Section>,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Exit with an error on warnings too, such as duplicates or a missing delivery.
+    #[arg(long)]
+    pub deny_warnings: bool,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ConvertArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub reporting: ReportingArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// The earlier input, or '-' for stdin.
+    pub old: String,
+    /// The later input.
+    pub new: String,
+    #[command(flatten)]
+    pub read: ReadArgs,
+    #[command(flatten)]
+    pub reporting: ReportingArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExplainArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct SlaArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Service-level commitments (JSON).
+    #[arg(long, value_name = "FILE")]
+    pub commitments: PathBuf,
+    #[command(flatten)]
+    pub reporting: ReportingArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// Input files and directories.
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    #[command(flatten)]
+    pub read: ReadArgs,
+    #[command(flatten)]
+    pub reporting: ReportingArgs,
+    /// Sections to include, comma-separated: total, layovers, stays, milestones, legs, longest. All by default.
+    #[arg(long, value_name = "LIST", value_delimiter = ',', value_parser = parse_section)]
+    pub sections: Vec<Section>,
+    /// Report format: text, json, csv, markdown or html. Defaults to the --combined file's extension.
+    #[arg(short, long, value_name = "FORMAT", value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+    /// Write one '<input>.report.<format>' per input into this directory.
+    #[arg(long, value_name = "DIR", conflicts_with = "combined")]
+    pub out_dir: Option<PathBuf>,
+    /// Write every report into this one file.
+    #[arg(long, value_name = "FILE")]
+    pub combined: Option<PathBuf>,
+}
+
+fn expects<T>(value: Option<T>, message: &str) -> Result<T, String> {
+    value.ok_or_else(|| message.to_string())
+}
+
+fn parse_columns(value: &str) -> Result<ColumnMap, String> {
+    ColumnMap::new(value)
+}
+
+fn parse_time_format(value: &str) -> Result<TimestampParser, String> {
+    Ok(TimestampParser::new(value))
+}
+
+fn parse_zone(value: &str) -> Result<LocalZone, String> {
+    expects(LocalZone::new(value), "expects UTC, an offset such as +02:00, or a country")
+}
+
+fn parse_fill(value: &str) -> Result<FillStrategy, String> {
+    expects(FillStrategy::new(value), "expects one of: forward, backward, nearest, unknown")
+}
+
+fn parse_level(value: &str) -> Result<LocationLevel, String> {
+    expects(LocationLevel::new(value), "expects one of: facility, city, region, country")
+}
+
+fn parse_leg_order(value: &str) -> Result<LegOrder, String> {
+    expects(LegOrder::new(value), "expects one of: time, duration")
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    expects(value.parse::<usize>().ok().filter(|x| *x > 0), "expects a number of legs")
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, String> {
+    expects(OutputFormat::new(value), "expects one of: text, json, csv, markdown, html")
+}
+
+fn parse_section(value: &str) -> Result<Section, String> {
+    expects(Section::new(value.trim()), "expects a list of: total, layovers, stays, milestones, legs, longest")
+}
+
+fn format_from_path(path: &Path) -> Option<OutputFormat> {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .and_then(OutputFormat::new)
+}
+
+/// Keeps only the sections listed, or every section when none are.
+fn with_sections(options: ReportOptions, sections: &[Section]) -> ReportOptions {
+    match sections.is_empty() {
+        true => options,
+        false => ReportOptions { sections: sections.to_vec(), ..options },
+    }
+}
+
+/// A config file that failed to load, named for the flag that gave it.
+fn loading(what: &str, path: &Path, err: impl Display) -> ShipError {
+    ShipError::Config(format!("Error loading {} from {}: {}", what, path.display(), err))
+}
+
+impl Command {
+    /// The read options of the subcommand.
+    pub fn read_args(&self) -> &ReadArgs {
+        match self {
+            Command::Report(args) => &args.input.read,
+            Command::Validate(args) => &args.input.read,
+            Command::Convert(args) => &args.input.read,
+            Command::Stats(args) => &args.input.read,
+            Command::Diff(args) => &args.read,
+            Command::Explain(args) => &args.input.read,
+            Command::Sla(args) => &args.input.read,
+            Command::Batch(args) => &args.read,
+        }
+    }
+}
+
+impl ReadArgs {
+    pub fn open(&self, file: &str) -> Result<Input, ShipError> {
+        let mut input = Input::open(file, self.input_format.as_deref())?;
+        input.columns = self.columns.clone();
+        input.time_format = self.time_format.clone();
+        input.strict = self.strict;
+
+        Ok(input)
+    }
+
+    /// Reads and builds one input. Problems with single lines go to the diagnostics unless they are strict.
+    pub fn load(&self, file: &str, diagnostics: &mut Diagnostics) -> Result<Vec<Shipment>, ShipError> {
+        let options = self.build_options()?;
+        let raw_string_data = reader_with(self.open(file)?, &options, diagnostics)?;
+        let shipments = shipment_builder(raw_string_data, &options, diagnostics)?;
+
+        if shipments.is_empty() {
+            return Err(ShipError::NoRecords);
+        }
+
+        Ok(shipments)
+    }
+
+    /// Loads the rules and aliases files, if any.
+    pub fn build_options(&self) -> Result<BuildOptions, ShipError> {
+        let classifier = match &self.rules {
+            Some(path) => Classifier::from_file(path).map_err(|err| loading("rules", path, err))?,
+            None => Classifier::default(),
+        };
+        let resolver = match &self.aliases {
+            Some(path) => CountryResolver::from_file(path).map_err(|err| loading("aliases", path, err))?,
+            None => CountryResolver::default(),
+        };
+
+        Ok(BuildOptions {
+            time_format: self.time_format.clone(),
+            classifier,
+            tracking: self.tracking.clone(),
+            fill: self.fill,
+            resolver,
+            timezone: self.timezone,
+            ..BuildOptions::default()
+        })
+    }
+}
+
+impl ReportingArgs {
+    /// Loads the calendar file, if any. Every section is included.
+    pub fn options(&self) -> Result<ReportOptions, ShipError> {
+        let calendar = match &self.calendar {
+            Some(path) => Some(BusinessCalendar::from_file(path).map_err(|err| loading("calendar", path, err))?),
+            None if self.business_time => Some(BusinessCalendar::default()),
+            None => None,
+        };
+
+        Ok(ReportOptions { group_by: self.group_by, leg_order: self.sort_legs, top_legs: self.top_legs, calendar, ..ReportOptions::default() })
+    }
+}
+
+impl ReportArgs {
+    pub fn options(&self) -> Result<ReportOptions, ShipError> {
+        Ok(with_sections(self.reporting.options()?, &self.sections))
+    }
+}
+
+impl SlaArgs {
+    pub fn config(&self) -> Result<SlaConfig, ShipError> {
+        SlaConfig::from_file(&self.commitments).map_err(|err| loading("SLA commitments", &self.commitments, err))
+    }
+}
+
+impl BatchArgs {
+    pub fn options(&self) -> Result<BatchOptions, ShipError> {
+        let output = match (&self.out_dir, &self.combined) {
+            (Some(dir), _) => BatchOutput::Directory(dir.clone()),
+            (None, Some(file)) => BatchOutput::Combined(file.clone()),
+            (None, None) => BatchOutput::Stdout,
+        };
+
+        let format = self.format
+            .or_else(|| self.combined.as_deref().and_then(format_from_path))
+            .unwrap_or(OutputFormat::Text);
+        let report = with_sections(self.reporting.options()?, &self.sections);
+
+        BatchOptions::from_paths(&self.paths, output, self.read.clone(), report, format)
+    }
+}
+
+impl OutputArgs {
+    /// `--format` when given, otherwise the one the output file's extension names, otherwise the default.
+    pub fn format_or(&self, default: OutputFormat) -> OutputFormat {
+        let from_file = self.output.as_deref().and_then(|x| format_from_path(Path::new(x)));
+
+        self.format.or(from_file).unwrap_or(default)
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format_or(OutputFormat::Text)
+    }
+
+    /// Writes to the output file, or prints when there is none.
+    pub fn write(&self, contents: &str) -> Result<(), ShipError> {
+        match &self.output {
+            Some(filename) => fs::write(filename, contents).map_err(|err| ShipError::from(err).in_file(filename)),
+            None => {
+                print!("{}", contents);
+                Ok(())
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use clap::CommandFactory;
+
+    fn parse(args: &str) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("shiptracker").chain(args.split_whitespace()))
+    }
+
+    #[test]
+    fn consistent_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn report_flags() {
+        let cli = parse("report tests/data/test_data.txt -o out.md --sort-legs duration --top-legs 3 --sections total,legs --timezone +02:00").unwrap();
+        let args = match cli.command {
+            Command::Report(args) => args,
+            other => panic!("parsed as {:?}", other),
+        };
+        let options = args.options().unwrap();
+
+        assert_eq!(args.input.file, "tests/data/test_data.txt");
+        assert_eq!(args.output.format(), OutputFormat::Markdown);
+        assert_eq!(options.leg_order, LegOrder::Duration);
+        assert_eq!(options.top_legs, Some(3));
+        assert_eq!(options.sections, vec![Section::Total, Section::Legs]);
+        assert!(args.input.read.build_options().unwrap().timezone.is_some());
+    }
+
+    #[test]
+    fn defaults() {
+        let args = match parse("convert -").unwrap().command {
+            Command::Convert(args) => args,
+            other => panic!("parsed as {:?}", other),
+        };
+
+        assert_eq!(args.output.format_or(OutputFormat::Json), OutputFormat::Json);
+        assert_eq!(args.input.read.fill, FillStrategy::Forward);
+        assert!(args.input.read.open("-").is_err());
+    }
+
+    #[test]
+    fn batch_flags() {
+        let args = match parse("batch tests/data --combined out.json --group-by city --sections total").unwrap().command {
+            Command::Batch(args) => args,
+            other => panic!("parsed as {:?}", other),
+        };
+        let options = args.options().unwrap();
+
+        assert_eq!(options.format, OutputFormat::Json);
+        assert_eq!(options.report.group_by, LocationLevel::City);
+        assert_eq!(options.report.sections, vec![Section::Total]);
+    }
+
+    #[test]
+    fn rejected_values() {
+        assert!(parse("report data.txt --sections total,weather").is_err());
+        assert!(parse("report data.txt --top-legs 0").is_err());
+        assert!(parse("report data.txt --timezone Mars").is_err());
+        assert!(parse("report - --input-format xml").is_err());
+        assert!(parse("report data.csv --columns weather=1").is_err());
+        assert!(parse("stats data.txt --sections total").is_err());
+        assert!(parse("batch data --out-dir a --combined b").is_err());
+        assert!(parse("data.txt").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use itertools::Itertools;
+
+use crate::models::diff::{DiffStatus, FigureChange, ReportDiff, ShipmentDiff};
+use crate::models::record::Record;
+use crate::models::report::{Report, ReportOptions};
+use crate::models::shipment::Shipment;
+
+/// The figures compared between two reports, by name. Layovers are named after their location and
+/// milestones by their label.
+fn figures(records: &[Record], options: &ReportOptions) -> Vec<(String, Option<i64>)> {
+    let report = match Report::with_options(records, options) {
+        Ok(report) => report,
+        Err(..) => return Vec::new(),
+    };
+    let mut figures = vec![("Total transit".to_string(), Some(report.total_transit_minutes))];
+
+    for layover in &report.layovers {
+        figures.push((format!("Layover in {}", layover.name), Some(layover.minutes)));
+    }
+    for milestone in &report.milestones {
+        figures.push((milestone.label.clone(), milestone.minutes));
+    }
+    figures.push(("Longest delay".to_string(), report.longest_delay.map(|x| x.minutes)));
+
+    figures
+}
+
+fn scans(records: &[Record]) -> Vec<String> {
+    records.iter()
+        .map(|x| format!("{} {}", x.datetime.to_rfc3339(), x.status))
+        .collect()
+}
+
+/// Compares one shipment as it appears in the old and the new input. Only figures that moved are kept.
+pub fn diff_shipment(before: &[Record], after: &[Record], options: &ReportOptions) -> Vec<FigureChange> {
+    let (old, new) = (figures(before, options), figures(after, options));
+    let lookup = |figures: &[(String, Option<i64>)], name: &str| figures.iter().find(|(x, _)| x == name).and_then(|(_, minutes)| *minutes);
+
+    old.iter().chain(new.iter())
+        .map(|(name, _)| name)
+        .unique()
+        .map(|name| FigureChange { name: name.clone(), before: lookup(&old, name), after: lookup(&new, name) })
+        .filter(|x| x.before != x.after)
+        .collect()
+}
+
+/// Matches shipments by tracking number: those in the old input in their order, then any that only
+/// the new input has.
+pub fn diff_shipments(before: &[Shipment], after: &[Shipment], options: &ReportOptions) -> ReportDiff {
+    let mut shipments = Vec::new();
+
+    for old in before {
+        let new = match after.iter().find(|x| x.tracking == old.tracking) {
+            Some(new) => new,
+            None => {
+                shipments.push(ShipmentDiff { tracking: old.tracking.clone(), status: DiffStatus::Removed, figures: Vec::new(), added_scans: Vec::new(), removed_scans: scans(&old.records) });
+                continue;
+            }
+        };
+        let (old_scans, new_scans) = (scans(&old.records), scans(&new.records));
+        let added_scans: Vec<String> = new_scans.iter().filter(|x| !old_scans.contains(x)).cloned().collect();
+        let removed_scans: Vec<String> = old_scans.iter().filter(|x| !new_scans.contains(x)).cloned().collect();
+        let figures = diff_shipment(&old.records, &new.records, options);
+        let status = if figures.is_empty() && added_scans.is_empty() && removed_scans.is_empty() { DiffStatus::Unchanged } else { DiffStatus::Changed };
+
+        shipments.push(ShipmentDiff { tracking: old.tracking.clone(), status, figures, added_scans, removed_scans });
+    }
+    for new in after.iter().filter(|x| !before.iter().any(|old| old.tracking == x.tracking)) {
+        shipments.push(ShipmentDiff { tracking: new.tracking.clone(), status: DiffStatus::Added, figures: Vec::new(), added_scans: scans(&new.records), removed_scans: Vec::new() });
+    }
+
+    let count = |status: DiffStatus| shipments.iter().filter(|x| x.status == status).count();
+    ReportDiff {
+        added: count(DiffStatus::Added),
+        removed: count(DiffStatus::Removed),
+        changed: count(DiffStatus::Changed),
+        unchanged: count(DiffStatus::Unchanged),
+        shipments,
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::fixtures::sample_records;
+
+    #[test]
+    fn moved_figures() {
+        let records = sample_records();
+        let truncated = records[..records.len() - 1].to_vec();
+        let before = vec![Shipment::new(Some("A1".to_string()), records.clone()), Shipment::new(Some("B2".to_string()), records.clone())];
+        let after = vec![Shipment::new(Some("A1".to_string()), truncated), Shipment::new(Some("C3".to_string()), records)];
+        let diff = diff_shipments(&before, &after, &ReportOptions::default());
+
+        assert_eq!((diff.changed, diff.removed, diff.added, diff.unchanged), (1, 1, 1, 0));
+        let changed = &diff.shipments[0];
+        let total = changed.figures.iter().find(|x| x.name == "Total transit").unwrap();
+        assert_eq!(total.before, Some(8332));
+        assert_eq!(total.delta(), Some(-770));
+        assert_eq!(changed.removed_scans, vec!["2017-01-26T10:05:33+01:00 Shipment delivered; BERLIN - GERMANY".to_string()]);
+        assert_eq!(diff.shipments[2].status, DiffStatus::Added);
+        assert!(diff_shipments(&before, &before, &ReportOptions::default()).is_empty());
+    }
+}
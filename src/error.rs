@@ -0,0 +1,211 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub const EXIT_USAGE: i32 = 1;
+pub const EXIT_BATCH_FAILED: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+pub const EXIT_DATA: i32 = 4;
+pub const EXIT_NO_RECORDS: i32 = 5;
+pub const EXIT_SLA_BREACHED: i32 = 6;
+
+#[derive(Debug)]
+pub enum ShipError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// A line that holds no timestamp the parser recognises.
+    UnrecognisedRecord(String),
+    /// A line with a timestamp but nothing after it.
+    MissingStatus(String),
+    InvalidTimestamp(String),
+    NoTimestamps,
+    NoRecords,
+    /// Bad flags, column mappings or rule files.
+    Config(String),
+    Invalid(String),
+    /// The number of shipments that missed their service-level commitment.
+    SlaBreached(usize),
+    /// Another error tied to the input file and, when known, the 1-based line it came from.
+    Context { file: Option<String>, line: Option<usize>, source: Box<ShipError> },
+}
+
+/// Line-level problems met during a run. In lenient mode they are kept as warnings and the line is
+/// skipped; in strict mode the first one stops the run.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub strict: bool,
+    pub file: Option<String>,
+    pub warnings: Vec<ShipError>,
+}
+
+impl ShipError {
+    pub fn at_line(self, line: usize) -> ShipError {
+        match self {
+            ShipError::Context { file, source, .. } => ShipError::Context { file, line: Some(line), source },
+            other => ShipError::Context { file: None, line: Some(line), source: Box::new(other) },
+        }
+    }
+
+    /// Names the input file, unless a file is already attached.
+    pub fn in_file(self, filename: &str) -> ShipError {
+        match self {
+            ShipError::Context { file: None, line, source } => ShipError::Context { file: Some(filename.to_string()), line, source },
+            ShipError::Context { .. } => self,
+            other => ShipError::Context { file: Some(filename.to_string()), line: None, source: Box::new(other) },
+        }
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            ShipError::Context { line, .. } => *line,
+            _ => None,
+        }
+    }
+
+    /// The error without the file and line it was tied to.
+    pub fn without_context(&self) -> &ShipError {
+        match self {
+            ShipError::Context { source, .. } => source.without_context(),
+            _ => self,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ShipError::Io(..) => EXIT_IO,
+            ShipError::Csv(err) if err.is_io_error() => EXIT_IO,
+            ShipError::Json(err) if err.is_io() => EXIT_IO,
+            ShipError::Config(..) => EXIT_USAGE,
+            ShipError::NoRecords => EXIT_NO_RECORDS,
+            ShipError::SlaBreached(..) => EXIT_SLA_BREACHED,
+            ShipError::Context { source, .. } => source.exit_code(),
+            _ => EXIT_DATA,
+        }
+    }
+}
+
+impl fmt::Display for ShipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShipError::Io(err) => write!(f, "I/O error: {}", err),
+            ShipError::Csv(err) => write!(f, "CSV error: {}", err),
+            ShipError::Json(err) => write!(f, "JSON error: {}", err),
+            ShipError::UnrecognisedRecord(line) => write!(f, "Unrecognised record: '{}'", line),
+            ShipError::MissingStatus(line) => write!(f, "Record has no status: '{}'", line),
+            ShipError::InvalidTimestamp(value) => write!(f, "Unrecognised timestamp: '{}'", value),
+            ShipError::NoTimestamps => write!(f, "No recognisable timestamps found."),
+            ShipError::NoRecords => write!(f, "No records found."),
+            ShipError::Config(message) | ShipError::Invalid(message) => write!(f, "{}", message),
+            ShipError::SlaBreached(count) => write!(f, "{} shipment(s) breached their SLA.", count),
+            ShipError::Context { file, line, source } => match (file, line) {
+                (Some(file), Some(line)) => write!(f, "{}:{}: {}", file, line, source),
+                (Some(file), None) => write!(f, "{}: {}", file, source),
+                (None, Some(line)) => write!(f, "line {}: {}", line, source),
+                (None, None) => write!(f, "{}", source),
+            },
+        }
+    }
+}
+
+impl Error for ShipError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShipError::Io(err) => Some(err),
+            ShipError::Csv(err) => Some(err),
+            ShipError::Json(err) => Some(err),
+            ShipError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ShipError {
+    fn from(err: io::Error) -> ShipError {
+        ShipError::Io(err)
+    }
+}
+
+impl From<csv::Error> for ShipError {
+    fn from(err: csv::Error) -> ShipError {
+        ShipError::Csv(err)
+    }
+}
+
+impl From<serde_json::Error> for ShipError {
+    fn from(err: serde_json::Error) -> ShipError {
+        ShipError::Json(err)
+    }
+}
+
+impl From<String> for ShipError {
+    fn from(message: String) -> ShipError {
+        ShipError::Invalid(message)
+    }
+}
+
+impl Diagnostics {
+    pub fn new(strict: bool) -> Diagnostics {
+        Diagnostics { strict, ..Diagnostics::default() }
+    }
+
+    /// Records a problem with a single line. Returns it as an error in strict mode so the caller
+    /// stops; otherwise keeps it and lets the caller skip the line.
+    pub fn report(&mut self, err: ShipError) -> Result<(), ShipError> {
+        let err = match &self.file {
+            Some(file) => err.in_file(file),
+            None => err,
+        };
+
+        if self.strict {
+            return Err(err);
+        }
+
+        self.warnings.push(err);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        if self.warnings.is_empty() {
+            return String::new();
+        }
+
+        let mut contents = format!("{} line(s) skipped:\n", self.warnings.len());
+        for warning in &self.warnings {
+            contents.push_str(&format!("  WARNING {}\n", warning));
+        }
+
+        contents
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn line_context() {
+        let err = ShipError::UnrecognisedRecord("Weather report".to_string()).at_line(4).in_file("a.txt");
+        assert_eq!(err.to_string(), "a.txt:4: Unrecognised record: 'Weather report'");
+        assert_eq!(err.line(), Some(4));
+        assert_eq!(err.without_context().to_string(), "Unrecognised record: 'Weather report'");
+        assert_eq!(err.exit_code(), EXIT_DATA);
+    }
+
+    #[test]
+    fn lenient_collects_warnings() {
+        let mut diagnostics = Diagnostics::new(false);
+        assert!(diagnostics.report(ShipError::NoTimestamps.at_line(2)).is_ok());
+        assert_eq!(diagnostics.warnings.len(), 1);
+
+        let mut diagnostics = Diagnostics::new(true);
+        assert!(diagnostics.report(ShipError::NoTimestamps.at_line(2)).is_err());
+    }
+}
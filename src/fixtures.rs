@@ -0,0 +1,22 @@
+use chrono::DateTime;
+
+use crate::builder::data_builder;
+use crate::models::input::Input;
+use crate::models::location::Location;
+use crate::models::record::Record;
+use crate::reader::reader;
+
+/// The records of `tests/data/test_data.txt`, built with the default options.
+pub fn sample_records() -> Vec<Record> {
+    let inp = Input::open("tests/data/test_data.txt", None).unwrap();
+    data_builder(reader(inp).unwrap()).unwrap()
+}
+
+/// A record at a `"%Y-%m-%d %H:%M:%S %z"` time and location; every other field is left as `Record::new` sets it.
+pub fn record_at(datetime: &str, status: &str, location: Option<Location>) -> Record {
+    let datetime = DateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S %z").unwrap();
+    let mut record = Record::new(datetime.naive_local(), status.to_string()).unwrap();
+    record.set_offset(*datetime.offset(), false);
+    record.set_location(location);
+    record
+}
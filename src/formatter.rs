@@ -1,32 +1,350 @@
-use crate::models::record::Record;
-use crate::calc::{calc_total_shipment_time, calc_layover_times, calc_longest_delay};
+use chrono::{DateTime, FixedOffset};
 
-pub fn format_total_shipment_time(records: &[Record]) -> String {
-    let total_mins = calc_total_shipment_time(records).unwrap();
-    format!("[{}:{}]", &total_mins/60, &total_mins%60)
+use crate::calc::location_name;
+use crate::models::{portfolio::Portfolio, record::Record, report::{Report, ShipmentState}, transfer::Transfer};
+use crate::models::diff::{DiffStatus, FigureChange, ReportDiff};
+use crate::models::sla::{BreachCause, SlaEvaluation};
+use crate::models::location::{Location, UNKNOWN_LOCATION};
+use crate::models::shipment::Shipment;
+use crate::models::stats::{Distribution, PortfolioStats, ShipmentStats};
+use crate::models::validation::{Severity, Validation};
+
+pub fn format_duration(minutes: i64) -> String {
+    format!("[{}:{}]", minutes/60, minutes%60)
+}
+
+/// Elapsed time, followed by the business time when the report has a calendar.
+pub fn format_elapsed(minutes: i64, business_minutes: Option<i64>) -> String {
+    match business_minutes {
+        Some(business) => format!("{} (business {})", format_duration(minutes), format_duration(business)),
+        None => format_duration(minutes),
+    }
+}
+
+pub fn format_total_shipment_time(report: &Report) -> String {
+    format_elapsed(report.total_transit_minutes, report.total_business_minutes)
+}
+
+pub fn format_location(record: &Record) -> String {
+    match &record.location {
+        Some(location) => location.to_string(),
+        None => UNKNOWN_LOCATION.to_string(),
+    }
+}
+
+pub fn format_layover_times(report: &Report) -> Vec<String> {
+    let mut fmt_times = Vec::new();
+
+    for layover in &report.layovers {
+        let visits = if layover.visits > 1 { format!(" ({} visits)", layover.visits) } else { String::new() };
+        let fmt_str = format!("{}: {}{}\n", layover.name, format_elapsed(layover.minutes, layover.business_minutes), visits);
+        fmt_times.push(fmt_str);
+    }
+
+    fmt_times
+}
+
+pub fn format_stays(report: &Report) -> Vec<String> {
+    let mut fmt_stays = Vec::new();
+
+    for stay in &report.stays {
+        let fmt_str = format!(
+            "{}: {}\n      Arrived:  {}\n      Departed: {}\n",
+            stay.name,
+            format_elapsed(stay.minutes, stay.business_minutes),
+            format_timestamp(&stay.arrival.datetime),
+            format_timestamp(&stay.departure.datetime),
+        );
+        fmt_stays.push(fmt_str);
+    }
+
+    fmt_stays
 }
 
-pub fn format_layover_times(records: &[Record]) -> Vec<String> {
-    let layover_times = calc_layover_times(records).unwrap();
+pub fn format_milestones(report: &Report) -> Vec<String> {
     let mut fmt_times = Vec::new();
 
-    for layover in layover_times {
-        let fmt_str = format!("{}: [{}:{}]\n", &layover.to.locale.unwrap(), &layover.minutes/60, &layover.minutes%60);
+    for milestone in &report.milestones {
+        let fmt_str = match milestone.minutes {
+            Some(mins) => format!("{}: {}\n", milestone.label, format_elapsed(mins, milestone.business_minutes)),
+            None => format!("{}: n/a\n", milestone.label),
+        };
         fmt_times.push(fmt_str);
     }
 
     fmt_times
 }
 
-pub fn format_longest_delay(records: &[Record]) -> String {
-    let transfer = calc_longest_delay(records).unwrap();
+pub fn format_timestamp(datetime: &DateTime<FixedOffset>) -> String {
+    format!("{} ({} UTC)", datetime.format("%Y-%m-%d %H:%M %:z"), datetime.naive_utc().format("%Y-%m-%d %H:%M"))
+}
+
+/// Each leg on two lines: the locations at the report's level with the duration, then the two statuses.
+pub fn format_legs(report: &Report) -> Vec<String> {
+    let name = |record: &Record| location_name(record, report.group_by).unwrap_or_else(|| UNKNOWN_LOCATION.to_string());
+    let mut fmt_legs = Vec::new();
+
+    for leg in &report.legs {
+        let fmt_str = format!(
+            "{} -> {}: {}\n      {} -> {}\n",
+            name(&leg.from),
+            name(&leg.to),
+            format_elapsed(leg.minutes, leg.business_minutes),
+            leg.from.status,
+            leg.to.status,
+        );
+        fmt_legs.push(fmt_str);
+    }
+
+    fmt_legs
+}
+
+pub fn format_longest_delay(report: &Report) -> String {
+    match &report.longest_delay {
+        Some(delay) => format_transfer(delay),
+        None => "n/a (awaiting first movement)".to_string(),
+    }
+}
+
+/// A line for shipments with a single scan so far; empty once the shipment has moved.
+pub fn format_state(report: &Report) -> String {
+    match report.state {
+        ShipmentState::AwaitingFirstMovement => format!("Awaiting first movement: one scan at {}\n", format_timestamp(&report.first_scan)),
+        _ => String::new(),
+    }
+}
+
+pub fn format_longest_legs(legs: &[Transfer]) -> Vec<String> {
+    legs.iter()
+        .enumerate()
+        .map(|(index, leg)| format!("{}. {}\n", index + 1, format_transfer(leg)))
+        .collect()
+}
+
+pub fn format_transfer(transfer: &Transfer) -> String {
     format!(
-        "From: {} - {}\nTo: {} - {}\nDuration: [{}:{}]",
-        &transfer.from.locale.unwrap(),
+        "From: {} - {} [{}]\n      {}\nTo: {} - {} [{}]\n      {}\nDuration: {}",
+        format_location(&transfer.from),
         &transfer.from.status,
-        &transfer.to.locale.unwrap(),
+        &transfer.from.kind,
+        format_timestamp(&transfer.from.datetime),
+        format_location(&transfer.to),
         &transfer.to.status,
-        &transfer.minutes/60,
-        &transfer.minutes%60,
+        &transfer.to.kind,
+        format_timestamp(&transfer.to.datetime),
+        format_elapsed(transfer.minutes, transfer.business_minutes),
         )
 }
+
+/// The closing summary of a multi-shipment report, listing any shipments that could not be reported on.
+pub fn format_portfolio_summary(portfolio: &Portfolio) -> String {
+    let summary = &portfolio.summary;
+    let transit = |minutes: Option<i64>| minutes.map(format_duration).unwrap_or_else(|| "n/a".to_string());
+    let mut contents = format!(
+        "Shipments: {} ({} reported, {} failed, {} delivered, {} awaiting first movement)\nRecords: {}\nTransit min/mean/max: {} / {} / {}\nSlowest shipment: {}\n",
+        summary.shipments,
+        summary.reported,
+        summary.failed,
+        summary.delivered,
+        summary.awaiting,
+        summary.records,
+        transit(summary.min_transit_minutes),
+        transit(summary.mean_transit_minutes),
+        transit(summary.max_transit_minutes),
+        summary.slowest.as_deref().unwrap_or("n/a"),
+    );
+
+    for failed in &portfolio.failed {
+        contents.push_str(&format!("  FAILED {} ({} records): {}\n", failed.tracking.as_deref().unwrap_or("untracked"), failed.records, failed.error));
+    }
+
+    contents
+}
+
+pub fn format_distribution(distribution: &Option<Distribution>) -> String {
+    match distribution {
+        Some(dist) => format!(
+            "mean {} median {} p90 {} p95 {} (n={})",
+            format_duration(dist.mean),
+            format_duration(dist.median),
+            format_duration(dist.p90),
+            format_duration(dist.p95),
+            dist.count,
+        ),
+        None => "n/a".to_string(),
+    }
+}
+
+fn format_shipment_stats(stats: &ShipmentStats, indent: &str) -> String {
+    let mut contents = format!(
+        "{0}Transit:     {1}\n{0}Longest leg: {2}\n",
+        indent,
+        format_distribution(&stats.transit),
+        format_distribution(&stats.longest_leg),
+    );
+
+    for layover in &stats.layovers {
+        contents.push_str(&format!("{}Layover in {}: {}\n", indent, layover.country, format_distribution(&Some(layover.minutes.clone()))));
+    }
+
+    contents
+}
+
+/// Transit, longest leg and per-country layover statistics, overall and then by lane and ship week.
+pub fn format_portfolio_stats(stats: &PortfolioStats) -> String {
+    let mut contents = format!("Overall ({} shipments):\n{}", stats.overall.shipments, format_shipment_stats(&stats.overall, "  "));
+
+    contents.push_str("By lane:\n");
+    for lane in &stats.lanes {
+        contents.push_str(&format!("  {} ({} shipments):\n{}", lane.key, lane.stats.shipments, format_shipment_stats(&lane.stats, "    ")));
+    }
+    contents.push_str("By ship week:\n");
+    for week in &stats.weeks {
+        contents.push_str(&format!("  {} ({} shipments):\n{}", week.key, week.stats.shipments, format_shipment_stats(&week.stats, "    ")));
+    }
+
+    contents
+}
+
+/// Where a location's country came from, e.g. `"USA" (ISO alpha-3 code), confidence 0.95`.
+pub fn format_provenance(location: &Location) -> String {
+    let provenance = match &location.provenance {
+        Some(provenance) => provenance,
+        None => return "given explicitly".to_string(),
+    };
+    let inherited = match (provenance.inherited, provenance.inherited_from) {
+        (true, Some(line)) => format!("inherited from line {}: ", line),
+        (true, None) => "inherited: ".to_string(),
+        (false, _) => String::new(),
+    };
+    let token = provenance.token.as_ref().map(|x| format!("\"{}\" ", x)).unwrap_or_default();
+
+    format!("{}{}({}), confidence {:.2}", inherited, token, provenance.rule, provenance.confidence)
+}
+
+/// Every record in input order, with the location it ended up with and how that was derived.
+pub fn format_explain(shipment: &Shipment) -> String {
+    let mut records: Vec<&Record> = shipment.records.iter().collect();
+    records.sort_by_key(|x| x.line);
+    let mut contents = String::new();
+
+    for (index, record) in records.iter().enumerate() {
+        let label = match record.line {
+            Some(line) => format!("Line {}", line),
+            None => format!("Record {}", index + 1),
+        };
+        let derivation = record.location.as_ref().map(format_provenance).unwrap_or_else(|| "no location".to_string());
+        contents.push_str(&format!("{}: {}\n      {} <- {}\n", label, record.status, format_location(record), derivation));
+    }
+
+    contents
+}
+
+/// One block per shipment: status and commitment, the limit against the elapsed time, and for a breach
+/// the leg or locale that took the most time.
+pub fn format_sla(evaluation: &SlaEvaluation) -> String {
+    let mut contents = String::new();
+
+    for result in &evaluation.results {
+        let margin = match result.margin_minutes {
+            x if x < 0 => format!("{} over", format_duration(-x)),
+            x => format!("{} to spare", format_duration(x)),
+        };
+        contents.push_str(&format!(
+            "{}: {} - {} ({})\n      Limit {}, elapsed {}, {}\n",
+            result.key(),
+            format!("{:?}", result.status).to_uppercase(),
+            result.commitment,
+            result.lane,
+            format_duration(result.limit_minutes),
+            format_duration(result.elapsed_minutes),
+            margin,
+        ));
+        match &result.cause {
+            Some(BreachCause::Leg { from, to, minutes }) => contents.push_str(&format!("      Cause: leg {} -> {} {}\n", from, to, format_duration(*minutes))),
+            Some(BreachCause::Locale { name, minutes }) => contents.push_str(&format!("      Cause: stay in {} {}\n", name, format_duration(*minutes))),
+            None => (),
+        }
+    }
+    for tracking in &evaluation.unmatched {
+        contents.push_str(&format!("{}: no matching commitment\n", tracking));
+    }
+    contents.push_str(&format!("{} met, {} pending, {} breached\n", evaluation.met, evaluation.pending, evaluation.breached));
+
+    contents
+}
+
+/// A moved figure such as `Total transit: [138:52] -> [126:02] (-[12:50])`.
+pub fn format_figure_change(change: &FigureChange) -> String {
+    let minutes = |x: Option<i64>| x.map(format_duration).unwrap_or_else(|| "n/a".to_string());
+    let delta = match change.delta() {
+        Some(delta) if delta < 0 => format!(" (-{})", format_duration(-delta)),
+        Some(delta) => format!(" (+{})", format_duration(delta)),
+        None => String::new(),
+    };
+
+    format!("{}: {} -> {}{}", change.name, minutes(change.before), minutes(change.after), delta)
+}
+
+/// Every shipment that differs, with its moved figures and its added (`+`) and removed (`-`) scans,
+/// then a count of each outcome.
+pub fn format_diff(diff: &ReportDiff) -> String {
+    let mut contents = String::new();
+
+    for shipment in diff.shipments.iter().filter(|x| x.status != DiffStatus::Unchanged) {
+        let status = match shipment.status {
+            DiffStatus::Added => "added",
+            DiffStatus::Removed => "removed",
+            _ => "changed",
+        };
+        contents.push_str(&format!("==== {} ({}) ====\n", shipment.key(), status));
+        for change in &shipment.figures {
+            contents.push_str(&format!("{}\n", format_figure_change(change)));
+        }
+        for scan in &shipment.added_scans {
+            contents.push_str(&format!("+ {}\n", scan));
+        }
+        for scan in &shipment.removed_scans {
+            contents.push_str(&format!("- {}\n", scan));
+        }
+    }
+    contents.push_str(&format!("Shipments: {} changed, {} added, {} removed, {} unchanged\n", diff.changed, diff.added, diff.removed, diff.unchanged));
+
+    contents
+}
+
+/// One line per issue in the `file:line: severity: message` form of a compiler, then the counts.
+/// "1 shipment", "2 shipments".
+fn count(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("{} {}", n, noun),
+        _ => format!("{} {}s", n, noun),
+    }
+}
+
+pub fn format_validation(validation: &Validation) -> String {
+    let mut contents = String::new();
+
+    for issue in &validation.issues {
+        let place = match issue.line {
+            Some(line) => format!("{}:{}", validation.file, line),
+            None => validation.file.clone(),
+        };
+        let severity = match issue.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let tracking = issue.tracking.as_ref().map(|x| format!("{}: ", x)).unwrap_or_default();
+        contents.push_str(&format!("{}: {}: {}{} [{}]\n", place, severity, tracking, issue.message, issue.kind.name()));
+    }
+    contents.push_str(&format!(
+        "{}: {} in {}, {}, {}\n",
+        validation.file,
+        count(validation.records, "record"),
+        count(validation.shipments, "shipment"),
+        count(validation.errors, "error"),
+        count(validation.warnings, "warning"),
+    ));
+
+    contents
+}
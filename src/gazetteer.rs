@@ -0,0 +1,350 @@
+use celes::Country;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::models::location::{Coordinates, MatchRule};
+
+/// Embedded places: kind, name as carriers print it, city, alpha-2 country, latitude and longitude.
+/// Airports are keyed by IATA code; hubs by the name carriers give them. Cities whose names are
+/// everyday words ("Nice", "Mobile") are left out so they don't match ordinary status text.
+const PLACES: &[(PlaceKind, &str, &str, &str, f64, f64)] = &[
+    (PlaceKind::City, "NEW YORK", "NEW YORK", "US", 40.71, -74.01),
+    (PlaceKind::City, "LOS ANGELES", "LOS ANGELES", "US", 34.05, -118.24),
+    (PlaceKind::City, "CHICAGO", "CHICAGO", "US", 41.88, -87.63),
+    (PlaceKind::City, "ATLANTA", "ATLANTA", "US", 33.75, -84.39),
+    (PlaceKind::City, "DALLAS", "DALLAS", "US", 32.78, -96.80),
+    (PlaceKind::City, "HOUSTON", "HOUSTON", "US", 29.76, -95.37),
+    (PlaceKind::City, "MIAMI", "MIAMI", "US", 25.76, -80.19),
+    (PlaceKind::City, "MEMPHIS", "MEMPHIS", "US", 35.15, -90.05),
+    (PlaceKind::City, "LOUISVILLE", "LOUISVILLE", "US", 38.25, -85.76),
+    (PlaceKind::City, "CINCINNATI", "CINCINNATI", "US", 39.10, -84.51),
+    (PlaceKind::City, "INDIANAPOLIS", "INDIANAPOLIS", "US", 39.77, -86.16),
+    (PlaceKind::City, "NEWARK", "NEWARK", "US", 40.74, -74.17),
+    (PlaceKind::City, "SEATTLE", "SEATTLE", "US", 47.61, -122.33),
+    (PlaceKind::City, "SAN FRANCISCO", "SAN FRANCISCO", "US", 37.77, -122.42),
+    (PlaceKind::City, "OAKLAND", "OAKLAND", "US", 37.80, -122.27),
+    (PlaceKind::City, "ANCHORAGE", "ANCHORAGE", "US", 61.22, -149.90),
+    (PlaceKind::City, "TORONTO", "TORONTO", "CA", 43.65, -79.38),
+    (PlaceKind::City, "MONTREAL", "MONTREAL", "CA", 45.50, -73.57),
+    (PlaceKind::City, "VANCOUVER", "VANCOUVER", "CA", 49.28, -123.12),
+    (PlaceKind::City, "MEXICO CITY", "MEXICO CITY", "MX", 19.43, -99.13),
+    (PlaceKind::City, "SAO PAULO", "SAO PAULO", "BR", -23.55, -46.63),
+    (PlaceKind::City, "LONDON", "LONDON", "GB", 51.51, -0.13),
+    (PlaceKind::City, "MANCHESTER", "MANCHESTER", "GB", 53.48, -2.24),
+    (PlaceKind::City, "DUBLIN", "DUBLIN", "IE", 53.35, -6.26),
+    (PlaceKind::City, "PARIS", "PARIS", "FR", 48.86, 2.35),
+    (PlaceKind::City, "LYON", "LYON", "FR", 45.76, 4.84),
+    (PlaceKind::City, "BERLIN", "BERLIN", "DE", 52.52, 13.40),
+    (PlaceKind::City, "LEIPZIG", "LEIPZIG", "DE", 51.34, 12.37),
+    (PlaceKind::City, "FRANKFURT", "FRANKFURT", "DE", 50.11, 8.68),
+    (PlaceKind::City, "COLOGNE", "COLOGNE", "DE", 50.94, 6.96),
+    (PlaceKind::City, "KOELN", "COLOGNE", "DE", 50.94, 6.96),
+    (PlaceKind::City, "HAMBURG", "HAMBURG", "DE", 53.55, 9.99),
+    (PlaceKind::City, "MUNICH", "MUNICH", "DE", 48.14, 11.58),
+    (PlaceKind::City, "MUENCHEN", "MUNICH", "DE", 48.14, 11.58),
+    (PlaceKind::City, "AMSTERDAM", "AMSTERDAM", "NL", 52.37, 4.90),
+    (PlaceKind::City, "ROTTERDAM", "ROTTERDAM", "NL", 51.92, 4.48),
+    (PlaceKind::City, "BRUSSELS", "BRUSSELS", "BE", 50.85, 4.35),
+    (PlaceKind::City, "LIEGE", "LIEGE", "BE", 50.63, 5.57),
+    (PlaceKind::City, "MADRID", "MADRID", "ES", 40.42, -3.70),
+    (PlaceKind::City, "BARCELONA", "BARCELONA", "ES", 41.39, 2.17),
+    (PlaceKind::City, "MILAN", "MILAN", "IT", 45.46, 9.19),
+    (PlaceKind::City, "ROME", "ROME", "IT", 41.90, 12.50),
+    (PlaceKind::City, "ZURICH", "ZURICH", "CH", 47.38, 8.54),
+    (PlaceKind::City, "VIENNA", "VIENNA", "AT", 48.21, 16.37),
+    (PlaceKind::City, "PRAGUE", "PRAGUE", "CZ", 50.08, 14.44),
+    (PlaceKind::City, "WARSAW", "WARSAW", "PL", 52.23, 21.01),
+    (PlaceKind::City, "COPENHAGEN", "COPENHAGEN", "DK", 55.68, 12.57),
+    (PlaceKind::City, "STOCKHOLM", "STOCKHOLM", "SE", 59.33, 18.07),
+    (PlaceKind::City, "ISTANBUL", "ISTANBUL", "TR", 41.01, 28.98),
+    (PlaceKind::City, "DUBAI", "DUBAI", "AE", 25.20, 55.27),
+    (PlaceKind::City, "DOHA", "DOHA", "QA", 25.29, 51.53),
+    (PlaceKind::City, "MUMBAI", "MUMBAI", "IN", 19.08, 72.88),
+    (PlaceKind::City, "NEW DELHI", "NEW DELHI", "IN", 28.61, 77.21),
+    (PlaceKind::City, "SINGAPORE", "SINGAPORE", "SG", 1.35, 103.82),
+    (PlaceKind::City, "HONG KONG", "HONG KONG", "HK", 22.32, 114.17),
+    (PlaceKind::City, "SHANGHAI", "SHANGHAI", "CN", 31.23, 121.47),
+    (PlaceKind::City, "SHENZHEN", "SHENZHEN", "CN", 22.54, 114.06),
+    (PlaceKind::City, "GUANGZHOU", "GUANGZHOU", "CN", 23.13, 113.26),
+    (PlaceKind::City, "BEIJING", "BEIJING", "CN", 39.90, 116.41),
+    (PlaceKind::City, "TOKYO", "TOKYO", "JP", 35.68, 139.69),
+    (PlaceKind::City, "SEOUL", "SEOUL", "KR", 37.57, 126.98),
+    (PlaceKind::City, "TAIPEI", "TAIPEI", "TW", 25.03, 121.57),
+    (PlaceKind::City, "BANGKOK", "BANGKOK", "TH", 13.76, 100.50),
+    (PlaceKind::City, "SYDNEY", "SYDNEY", "AU", -33.87, 151.21),
+    (PlaceKind::City, "MELBOURNE", "MELBOURNE", "AU", -37.81, 144.96),
+    (PlaceKind::City, "AUCKLAND", "AUCKLAND", "NZ", -36.85, 174.76),
+    (PlaceKind::City, "JOHANNESBURG", "JOHANNESBURG", "ZA", -26.20, 28.05),
+    (PlaceKind::Hub, "WORLDPORT", "LOUISVILLE", "US", 38.17, -85.74),
+    (PlaceKind::Hub, "WORLD HUB", "MEMPHIS", "US", 35.04, -89.98),
+    (PlaceKind::Hub, "HEATHROW", "LONDON", "GB", 51.47, -0.45),
+    (PlaceKind::Hub, "EAST MIDLANDS", "EAST MIDLANDS", "GB", 52.83, -1.33),
+    (PlaceKind::Hub, "SCHIPHOL", "AMSTERDAM", "NL", 52.31, 4.76),
+    (PlaceKind::Hub, "ROISSY", "PARIS", "FR", 49.01, 2.55),
+    (PlaceKind::Hub, "CHANGI", "SINGAPORE", "SG", 1.36, 103.99),
+    (PlaceKind::Hub, "INCHEON", "SEOUL", "KR", 37.46, 126.44),
+    (PlaceKind::Hub, "NARITA", "TOKYO", "JP", 35.77, 140.39),
+    (PlaceKind::Airport, "JFK", "NEW YORK", "US", 40.64, -73.78),
+    (PlaceKind::Airport, "EWR", "NEWARK", "US", 40.69, -74.17),
+    (PlaceKind::Airport, "LAX", "LOS ANGELES", "US", 33.94, -118.41),
+    (PlaceKind::Airport, "ORD", "CHICAGO", "US", 41.97, -87.91),
+    (PlaceKind::Airport, "ATL", "ATLANTA", "US", 33.64, -84.43),
+    (PlaceKind::Airport, "DFW", "DALLAS", "US", 32.90, -97.04),
+    (PlaceKind::Airport, "MIA", "MIAMI", "US", 25.80, -80.29),
+    (PlaceKind::Airport, "MEM", "MEMPHIS", "US", 35.04, -89.98),
+    (PlaceKind::Airport, "SDF", "LOUISVILLE", "US", 38.17, -85.74),
+    (PlaceKind::Airport, "CVG", "CINCINNATI", "US", 39.05, -84.67),
+    (PlaceKind::Airport, "SFO", "SAN FRANCISCO", "US", 37.62, -122.38),
+    (PlaceKind::Airport, "OAK", "OAKLAND", "US", 37.72, -122.22),
+    (PlaceKind::Airport, "ANC", "ANCHORAGE", "US", 61.17, -150.00),
+    (PlaceKind::Airport, "YYZ", "TORONTO", "CA", 43.68, -79.63),
+    (PlaceKind::Airport, "YUL", "MONTREAL", "CA", 45.47, -73.74),
+    (PlaceKind::Airport, "YVR", "VANCOUVER", "CA", 49.19, -123.18),
+    (PlaceKind::Airport, "GRU", "SAO PAULO", "BR", -23.43, -46.47),
+    (PlaceKind::Airport, "LHR", "LONDON", "GB", 51.47, -0.45),
+    (PlaceKind::Airport, "EMA", "EAST MIDLANDS", "GB", 52.83, -1.33),
+    (PlaceKind::Airport, "DUB", "DUBLIN", "IE", 53.43, -6.25),
+    (PlaceKind::Airport, "CDG", "PARIS", "FR", 49.01, 2.55),
+    (PlaceKind::Airport, "BER", "BERLIN", "DE", 52.36, 13.50),
+    (PlaceKind::Airport, "LEJ", "LEIPZIG", "DE", 51.42, 12.24),
+    (PlaceKind::Airport, "FRA", "FRANKFURT", "DE", 50.04, 8.56),
+    (PlaceKind::Airport, "CGN", "COLOGNE", "DE", 50.87, 7.14),
+    (PlaceKind::Airport, "HAM", "HAMBURG", "DE", 53.63, 9.99),
+    (PlaceKind::Airport, "MUC", "MUNICH", "DE", 48.35, 11.79),
+    (PlaceKind::Airport, "AMS", "AMSTERDAM", "NL", 52.31, 4.76),
+    (PlaceKind::Airport, "BRU", "BRUSSELS", "BE", 50.90, 4.48),
+    (PlaceKind::Airport, "LGG", "LIEGE", "BE", 50.64, 5.44),
+    (PlaceKind::Airport, "MAD", "MADRID", "ES", 40.49, -3.57),
+    (PlaceKind::Airport, "BCN", "BARCELONA", "ES", 41.30, 2.08),
+    (PlaceKind::Airport, "MXP", "MILAN", "IT", 45.63, 8.72),
+    (PlaceKind::Airport, "FCO", "ROME", "IT", 41.80, 12.25),
+    (PlaceKind::Airport, "ZRH", "ZURICH", "CH", 47.46, 8.55),
+    (PlaceKind::Airport, "VIE", "VIENNA", "AT", 48.11, 16.57),
+    (PlaceKind::Airport, "PRG", "PRAGUE", "CZ", 50.10, 14.26),
+    (PlaceKind::Airport, "WAW", "WARSAW", "PL", 52.17, 20.97),
+    (PlaceKind::Airport, "CPH", "COPENHAGEN", "DK", 55.62, 12.66),
+    (PlaceKind::Airport, "ARN", "STOCKHOLM", "SE", 59.65, 17.92),
+    (PlaceKind::Airport, "IST", "ISTANBUL", "TR", 41.26, 28.74),
+    (PlaceKind::Airport, "DXB", "DUBAI", "AE", 25.25, 55.36),
+    (PlaceKind::Airport, "DOH", "DOHA", "QA", 25.27, 51.61),
+    (PlaceKind::Airport, "BOM", "MUMBAI", "IN", 19.09, 72.87),
+    (PlaceKind::Airport, "DEL", "NEW DELHI", "IN", 28.56, 77.10),
+    (PlaceKind::Airport, "SIN", "SINGAPORE", "SG", 1.36, 103.99),
+    (PlaceKind::Airport, "HKG", "HONG KONG", "HK", 22.31, 113.91),
+    (PlaceKind::Airport, "PVG", "SHANGHAI", "CN", 31.14, 121.81),
+    (PlaceKind::Airport, "SZX", "SHENZHEN", "CN", 22.64, 113.81),
+    (PlaceKind::Airport, "PEK", "BEIJING", "CN", 40.08, 116.58),
+    (PlaceKind::Airport, "NRT", "TOKYO", "JP", 35.77, 140.39),
+    (PlaceKind::Airport, "ICN", "SEOUL", "KR", 37.46, 126.44),
+    (PlaceKind::Airport, "TPE", "TAIPEI", "TW", 25.08, 121.23),
+    (PlaceKind::Airport, "BKK", "BANGKOK", "TH", 13.69, 100.75),
+    (PlaceKind::Airport, "SYD", "SYDNEY", "AU", -33.94, 151.18),
+    (PlaceKind::Airport, "MEL", "MELBOURNE", "AU", -37.67, 144.84),
+    (PlaceKind::Airport, "AKL", "AUCKLAND", "NZ", -37.01, 174.79),
+    (PlaceKind::Airport, "JNB", "JOHANNESBURG", "ZA", -26.14, 28.25),
+];
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaceKind {
+    City,
+    Hub,
+    Airport,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Place {
+    pub kind: PlaceKind,
+    pub name: &'static str,
+    pub city: &'static str,
+    pub country: Country,
+    pub coordinates: Coordinates,
+}
+
+/// A place found in a piece of text, with the rule that found it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PlaceMatch<'a> {
+    pub place: &'a Place,
+    pub rule: MatchRule,
+    /// The words that matched, as written in the text.
+    pub token: String,
+}
+
+/// Offline lookup of cities, carrier hubs and IATA airport codes for scans that name no country, such
+/// as "Arrived at LEIPZIG HUB" or "Processed at CVG GATEWAY". Names match in any case. Airport codes
+/// only match as upper-case words where a place can be (see `place_starts`), and a misspelt name
+/// ("LIEPZIG") only matches there too, as capitalised words close enough to a name: one edit for
+/// names of five letters or more, two from nine.
+#[derive(Debug, Clone)]
+pub struct Gazetteer {
+    places: Vec<Place>,
+}
+
+impl Default for Gazetteer {
+    fn default() -> Gazetteer {
+        let places = PLACES.iter()
+            .map(|(kind, name, city, alpha2, latitude, longitude)| Place {
+                kind: *kind,
+                name,
+                city,
+                country: Country::from_alpha2(alpha2).unwrap(),
+                coordinates: Coordinates { latitude: *latitude, longitude: *longitude },
+            })
+            .collect();
+
+        Gazetteer { places }
+    }
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|x: char| !x.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Where a place can start in a status: right after at/in/to/from ("Arrived at LIEPZIG HUB"), or at
+/// the start of the segment after the last ',' or ';' ("customs office; Maimi"). A lone word is a
+/// place of its own. Anywhere else a capitalised word is more likely a verb or a name ("Signed By PARKS").
+fn place_starts(text: &str, words: &[&str]) -> Vec<usize> {
+    if words.len() == 1 {
+        return vec![0];
+    }
+
+    let mut starts: Vec<usize> = words.iter()
+        .positions(|x| ["at", "in", "to", "from"].contains(&x.to_lowercase().as_str()))
+        .map(|x| x + 1)
+        .filter(|x| *x < words.len())
+        .collect();
+    if let Some(index) = text.rfind([',', ';']) {
+        let trailing = self::words(&text[index + 1..]).len();
+        if trailing > 0 {
+            starts.push(words.len() - trailing);
+        }
+    }
+
+    starts
+}
+
+fn is_capitalised(word: &str) -> bool {
+    word.starts_with(char::is_uppercase)
+}
+
+fn allowed_edits(name: &str) -> usize {
+    match name.chars().filter(|x| x.is_alphanumeric()).count() {
+        0..=4 => 0,
+        5..=8 => 1,
+        _ => 2,
+    }
+}
+
+/// Edit distance over characters, counting a swap of two neighbours ("LIEPZIG") as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d: Vec<Vec<usize>> = (0..=a.len())
+        .map(|i| (0..=b.len()).map(|j| if i == 0 { j } else if j == 0 { i } else { 0 }).collect())
+        .collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+impl Gazetteer {
+    pub fn places(&self) -> &[Place] {
+        &self.places
+    }
+
+    fn named(&self) -> impl Iterator<Item = &Place> {
+        self.places.iter().filter(|x| x.kind != PlaceKind::Airport)
+    }
+
+    /// The place named in the text, preferring an airport code, then the last exact name (the longest
+    /// at each position), then the closest misspelling.
+    pub fn find(&self, text: &str) -> Option<PlaceMatch<'_>> {
+        let words = words(text);
+        let starts = place_starts(text, &words);
+
+        let airport = starts.iter()
+            .flat_map(|start| &words[*start..(start + 3).min(words.len())])
+            .rev()
+            .filter(|x| x.len() == 3 && x.chars().all(|x| x.is_ascii_uppercase()))
+            .find_map(|word| self.places.iter().find(|x| x.kind == PlaceKind::Airport && x.name == *word));
+        if let Some(place) = airport {
+            return Some(PlaceMatch { place, rule: MatchRule::Iata, token: place.name.to_string() });
+        }
+
+        for start in (0..words.len()).rev() {
+            for len in (1..=3.min(words.len() - start)).rev() {
+                let token = words[start..start + len].join(" ");
+                if let Some(place) = self.named().find(|x| x.name == token.to_uppercase()) {
+                    return Some(PlaceMatch { place, rule: MatchRule::Gazetteer, token });
+                }
+            }
+        }
+
+        starts.iter()
+            .flat_map(|start| (1..=3.min(words.len() - start)).map(move |len| (*start, len)))
+            .filter(|(start, len)| words[*start..start + len].iter().all(|x| is_capitalised(x)))
+            .flat_map(|(start, len)| {
+                let token = words[start..start + len].join(" ");
+                self.named()
+                    .map(|place| (edit_distance(&token.to_uppercase(), place.name), place, token.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .filter(|(distance, place, _)| *distance <= allowed_edits(place.name))
+            .min_by_key(|(distance, _, _)| *distance)
+            .map(|(_, place, token)| PlaceMatch { place, rule: MatchRule::Fuzzy, token })
+    }
+
+    /// Coordinates for a city already resolved to a country, e.g. "LEIPZIG" in Germany.
+    pub fn coordinates(&self, city: &str, country: &Country) -> Option<Coordinates> {
+        let city = city.to_uppercase();
+        self.places.iter()
+            .find(|x| (x.name == city || x.city == city) && x.country == *country)
+            .map(|x| x.coordinates)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn find_places() {
+        let gazetteer = Gazetteer::default();
+        let find = |x: &str| gazetteer.find(x).map(|x| (x.place.city, x.place.country.alpha2, x.rule));
+
+        assert_eq!(find("Arrived at LEIPZIG HUB"), Some(("LEIPZIG", "DE", MatchRule::Gazetteer)));
+        assert_eq!(find("Processed at CVG GATEWAY"), Some(("CINCINNATI", "US", MatchRule::Iata)));
+        assert_eq!(find("Departed from Hong Kong"), Some(("HONG KONG", "HK", MatchRule::Gazetteer)));
+        assert_eq!(find("Arrived at UPS Worldport"), Some(("LOUISVILLE", "US", MatchRule::Gazetteer)));
+        assert_eq!(find("Arrived at LIEPZIG HUB"), Some(("LEIPZIG", "DE", MatchRule::Fuzzy)));
+        assert_eq!(find("Package moved to customs office; Maimi"), Some(("MIAMI", "US", MatchRule::Fuzzy)));
+        assert_eq!(find("Customs status updated;"), None);
+        assert_eq!(find("Parts sorted at facility"), None);
+        assert_eq!(find("Delivered, Signed By PARKS"), None);
+        assert_eq!(find("SHIPMENT DEL TO HAM"), Some(("HAMBURG", "DE", MatchRule::Iata)));
+        assert_eq!(find("CVG"), Some(("CINCINNATI", "US", MatchRule::Iata)));
+    }
+
+    #[test]
+    fn coordinates_by_city() {
+        let gazetteer = Gazetteer::default();
+        let berlin = gazetteer.coordinates("Berlin", &Country::germany()).unwrap();
+
+        assert!((berlin.latitude - 52.52).abs() < 0.01);
+        assert_eq!(gazetteer.coordinates("Berlin", &Country::canada()), None);
+        assert_eq!(edit_distance("LIEPZIG", "LEIPZIG"), 1);
+    }
+}
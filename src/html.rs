@@ -0,0 +1,261 @@
+use std::io::Write;
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, Offset, TimeZone, Utc};
+use itertools::Itertools;
+
+use crate::error::ShipError;
+use crate::formatter::{format_duration, format_location, format_timestamp, format_total_shipment_time};
+use crate::models::portfolio::Portfolio;
+use crate::models::report::{Report, Section, ShipmentState};
+use crate::render::ReportRenderer;
+
+/// A single self-contained HTML page: inline CSS, an inline SVG timeline and a few lines of script
+/// to sort the layover table. Nothing is fetched, so it can be attached to an email as is.
+pub struct HtmlRenderer;
+
+const PALETTE: [&str; 8] = ["#4e79a7", "#f28e2b", "#59a14f", "#b07aa1", "#76b7b2", "#edc948", "#9c755f", "#bab0ac"];
+const WIDTH: f64 = 960.0;
+const MARGIN: f64 = 20.0;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin:1em 0}th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}\
+th.sortable{cursor:pointer;background:#f3f3f3}th.sortable:after{content:' \\2195';color:#999}\
+td.num{text-align:right}.delay{color:#c0392b}";
+
+const SCRIPT: &str = "document.querySelectorAll('table.sortable').forEach(function(table){\
+table.querySelectorAll('th.sortable').forEach(function(th){th.addEventListener('click',function(){\
+var col=th.cellIndex,body=table.tBodies[0],rows=Array.from(body.rows),asc=th.dataset.dir!=='asc';th.dataset.dir=asc?'asc':'desc';\
+rows.sort(function(a,b){var x=a.cells[col].dataset.sort||'',y=b.cells[col].dataset.sort||'';\
+var n=parseFloat(x)-parseFloat(y);var r=isNaN(n)?x.localeCompare(y):n;return asc?r:-r;});\
+rows.forEach(function(row){body.appendChild(row);});});});});";
+
+/// An element id for a tracking number: letters and digits kept, everything else dashed.
+fn anchor(text: &str) -> String {
+    let id: String = text.chars().map(|x| if x.is_ascii_alphanumeric() { x } else { '-' }).collect();
+    format!("shipment-{}", id)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+struct Scale {
+    start: DateTime<FixedOffset>,
+    seconds: f64,
+}
+
+impl Scale {
+    fn new(report: &Report) -> Scale {
+        let seconds = (report.last_scan - report.first_scan).num_seconds().max(1) as f64;
+        Scale { start: report.first_scan, seconds }
+    }
+
+    fn x(&self, datetime: &DateTime<FixedOffset>) -> f64 {
+        MARGIN + (*datetime - self.start).num_seconds() as f64 / self.seconds * (WIDTH - 2.0 * MARGIN)
+    }
+}
+
+fn write_timeline(report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+    let scale = Scale::new(report);
+    let names: Vec<&String> = report.stays.iter().map(|x| &x.name).unique().collect();
+    let color = |name: &String| PALETTE[names.iter().position(|x| *x == name).unwrap_or(0) % PALETTE.len()];
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"150\" viewBox=\"0 0 {} 150\" role=\"img\">", WIDTH, WIDTH)?;
+
+    if let Some(delay) = &report.longest_delay {
+        let (from, to) = (scale.x(&delay.from.datetime), scale.x(&delay.to.datetime));
+        writeln!(out, "<rect x=\"{:.1}\" y=\"10\" width=\"{:.1}\" height=\"100\" fill=\"#e74c3c\" fill-opacity=\"0.15\" stroke=\"#c0392b\" stroke-dasharray=\"4 2\"><title>Longest delay {}</title></rect>",
+                 from, (to - from).max(1.0), format_duration(delay.minutes))?;
+        writeln!(out, "<text x=\"{:.1}\" y=\"24\" font-size=\"11\" fill=\"#c0392b\">Longest delay {}</text>", from + 4.0, format_duration(delay.minutes))?;
+    }
+
+    for stay in &report.stays {
+        let (start, end) = (scale.x(&stay.arrival.datetime), scale.x(&stay.departure.datetime));
+        writeln!(out, "<rect x=\"{:.1}\" y=\"40\" width=\"{:.1}\" height=\"24\" rx=\"3\" fill=\"{}\"><title>{}: {}</title></rect>",
+                 start, (end - start).max(2.0), color(&stay.name), escape(&stay.name), format_duration(stay.minutes))?;
+    }
+
+    writeln!(out, "<line x1=\"{}\" y1=\"85\" x2=\"{}\" y2=\"85\" stroke=\"#888\"/>", MARGIN, WIDTH - MARGIN)?;
+    for record in &report.records {
+        writeln!(out, "<circle cx=\"{:.1}\" cy=\"85\" r=\"4\" fill=\"#222\"><title>{} - {} ({})</title></circle>",
+                 scale.x(&record.datetime), escape(&format_timestamp(&record.datetime)), escape(&record.status), record.kind)?;
+    }
+
+    let mut day = report.first_scan.naive_utc().date().and_time(NaiveTime::MIN) + Duration::days(1);
+    while day < report.last_scan.naive_utc() {
+        let x = scale.x(&Utc.fix().from_utc_datetime(&day));
+        writeln!(out, "<line x1=\"{:.1}\" y1=\"80\" x2=\"{:.1}\" y2=\"90\" stroke=\"#888\"/><text x=\"{:.1}\" y=\"105\" font-size=\"10\" text-anchor=\"middle\">{}</text>",
+                 x, x, x, day.format("%m-%d"))?;
+        day += Duration::days(1);
+    }
+
+    for (index, name) in names.iter().enumerate() {
+        let x = MARGIN + index as f64 * 180.0;
+        writeln!(out, "<rect x=\"{:.1}\" y=\"125\" width=\"12\" height=\"12\" fill=\"{}\"/><text x=\"{:.1}\" y=\"136\" font-size=\"11\">{}</text>",
+                 x, color(name), x + 16.0, escape(name))?;
+    }
+
+    writeln!(out, "</svg>")?;
+    Ok(())
+}
+
+fn write_head(title: &str, out: &mut dyn Write) -> Result<(), ShipError> {
+    writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>{1}</style>\n</head>\n<body>\n<h1>{0}</h1>", title, STYLE)?;
+    Ok(())
+}
+
+fn write_foot(out: &mut dyn Write) -> Result<(), ShipError> {
+    writeln!(out, "<script>{}</script>\n</body>\n</html>", SCRIPT)?;
+    Ok(())
+}
+
+/// Everything below the page title, so a portfolio can stack several reports on one page.
+fn write_sections(report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+    if report.shows(Section::Total) {
+        if report.state == ShipmentState::AwaitingFirstMovement {
+            writeln!(out, "<p><strong>Awaiting first movement:</strong> one scan so far.</p>")?;
+        }
+        writeln!(out, "<p><strong>Total transit time:</strong> {} ({} to {})</p>",
+                 format_total_shipment_time(report), escape(&format_timestamp(&report.first_scan)), escape(&format_timestamp(&report.last_scan)))?;
+    }
+
+    if report.shows(Section::Stays) {
+        writeln!(out, "<h2>Timeline</h2>")?;
+        write_timeline(report, out)?;
+    }
+
+    if report.shows(Section::Layovers) {
+        writeln!(out, "<h2>Layovers</h2>\n<table class=\"sortable\">\n<thead><tr><th class=\"sortable\">Location</th><th class=\"sortable\">Visits</th><th class=\"sortable\">Dwell</th></tr></thead>\n<tbody>")?;
+        for layover in &report.layovers {
+            writeln!(out, "<tr><td data-sort=\"{0}\">{0}</td><td class=\"num\" data-sort=\"{1}\">{1}</td><td class=\"num\" data-sort=\"{2}\">{3}</td></tr>",
+                     escape(&layover.name), layover.visits, layover.minutes, format_duration(layover.minutes))?;
+        }
+        writeln!(out, "</tbody>\n</table>")?;
+    }
+
+    if report.shows(Section::Stays) {
+        writeln!(out, "<h2>Stays</h2>\n<table>\n<thead><tr><th>Location</th><th>Arrived</th><th>Departed</th><th>Dwell</th></tr></thead>\n<tbody>")?;
+        for stay in &report.stays {
+            writeln!(out, "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>",
+                     escape(&stay.name), escape(&format_timestamp(&stay.arrival.datetime)), escape(&format_timestamp(&stay.departure.datetime)), format_duration(stay.minutes))?;
+        }
+        writeln!(out, "</tbody>\n</table>")?;
+    }
+
+    if report.shows(Section::Milestones) {
+        writeln!(out, "<h2>Milestones</h2>\n<table>\n<tbody>")?;
+        for milestone in &report.milestones {
+            let duration = milestone.minutes.map(format_duration).unwrap_or_else(|| "n/a".to_string());
+            writeln!(out, "<tr><td>{}</td><td class=\"num\">{}</td></tr>", escape(&milestone.label), duration)?;
+        }
+        writeln!(out, "</tbody>\n</table>")?;
+    }
+
+    if report.shows(Section::Legs) {
+        writeln!(out, "<h2>Legs</h2>\n<table class=\"sortable\">\n<thead><tr><th class=\"sortable\">Departed</th><th class=\"sortable\">From</th><th class=\"sortable\">To</th><th>Statuses</th><th class=\"sortable\">Duration</th></tr></thead>\n<tbody>")?;
+        for leg in &report.legs {
+            let class = if Some(leg) == report.longest_delay.as_ref() { " class=\"delay\"" } else { "" };
+            writeln!(out, "<tr{0}><td data-sort=\"{1}\">{2}</td><td data-sort=\"{3}\">{3}</td><td data-sort=\"{4}\">{4}</td><td>{5} &rarr; {6}</td><td class=\"num\" data-sort=\"{7}\">{8}</td></tr>",
+                     class, leg.from.datetime.timestamp(), escape(&format_timestamp(&leg.from.datetime)), escape(&format_location(&leg.from)), escape(&format_location(&leg.to)),
+                     escape(&leg.from.status), escape(&leg.to.status), leg.minutes, format_duration(leg.minutes))?;
+        }
+        writeln!(out, "</tbody>\n</table>")?;
+    }
+
+    if report.shows(Section::Longest) {
+        let longest = report.longest();
+        match longest.len() {
+            0 => writeln!(out, "<h2>Longest delay: n/a (awaiting first movement)</h2>")?,
+            1 => writeln!(out, "<h2 class=\"delay\">Longest delay: {}</h2>", format_duration(longest[0].minutes))?,
+            count => writeln!(out, "<h2 class=\"delay\">The {} longest legs</h2>", count)?,
+        }
+        for leg in &longest {
+            writeln!(out, "<ul>\n<li><strong>From:</strong> {} - {} ({})</li>", escape(&format_location(&leg.from)), escape(&leg.from.status), escape(&format_timestamp(&leg.from.datetime)))?;
+            writeln!(out, "<li><strong>To:</strong> {} - {} ({})</li>\n<li><strong>Duration:</strong> {}</li>\n</ul>", escape(&format_location(&leg.to)), escape(&leg.to.status), escape(&format_timestamp(&leg.to.datetime)), format_duration(leg.minutes))?;
+        }
+    }
+
+    Ok(())
+}
+
+impl ReportRenderer for HtmlRenderer {
+    fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+        write_head("Shipment report", out)?;
+        write_sections(report, out)?;
+        write_foot(out)
+    }
+
+    /// One page: a sortable table of shipments, then each shipment's sections under its tracking number.
+    fn render_portfolio(&self, portfolio: &Portfolio, out: &mut dyn Write) -> Result<(), ShipError> {
+        let summary = &portfolio.summary;
+        write_head("Shipment portfolio", out)?;
+        writeln!(out, "<p><strong>Shipments:</strong> {} ({} reported, {} failed, {} delivered)</p>", summary.shipments, summary.reported, summary.failed, summary.delivered)?;
+
+        writeln!(out, "<table class=\"sortable\">\n<thead><tr><th class=\"sortable\">Tracking</th><th class=\"sortable\">Records</th><th class=\"sortable\">Transit</th></tr></thead>\n<tbody>")?;
+        for shipment in &portfolio.shipments {
+            let minutes = shipment.report.total_transit_minutes;
+            writeln!(out, "<tr><td data-sort=\"{0}\"><a href=\"#{1}\">{0}</a></td><td class=\"num\" data-sort=\"{2}\">{2}</td><td class=\"num\" data-sort=\"{3}\">{4}</td></tr>",
+                     escape(shipment.key()), anchor(shipment.key()), shipment.report.records.len(), minutes, format_duration(minutes))?;
+        }
+        for failed in &portfolio.failed {
+            writeln!(out, "<tr class=\"delay\"><td data-sort=\"{0}\">{0}</td><td class=\"num\" data-sort=\"{1}\">{1}</td><td data-sort=\"\">{2}</td></tr>",
+                     escape(failed.tracking.as_deref().unwrap_or("untracked")), failed.records, escape(&failed.error))?;
+        }
+        writeln!(out, "</tbody>\n</table>")?;
+
+        for shipment in &portfolio.shipments {
+            writeln!(out, "<section id=\"{}\">\n<h1>{}</h1>", anchor(shipment.key()), escape(shipment.key()))?;
+            write_sections(&shipment.report, out)?;
+            writeln!(out, "</section>")?;
+        }
+
+        write_foot(out)
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::render::render_to_string;
+    use crate::{fixtures::sample_records, models::location::LocationLevel};
+
+    #[test]
+    fn escape_markup() {
+        assert_eq!(escape("<b>\"A & B\"</b>"), "&lt;b&gt;&quot;A &amp; B&quot;&lt;/b&gt;");
+    }
+
+    #[test]
+    fn self_contained_page() {
+        let records = sample_records();
+        let report = Report::new(&records, LocationLevel::Country).unwrap();
+        let html = render_to_string(&HtmlRenderer, &report).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert_eq!(html.matches("<circle").count(), records.len());
+        assert_eq!(html.matches("rx=\"3\"").count(), report.stays.len());
+        assert!(html.contains("Longest delay [44:43]"));
+        assert!(!html.contains("src=\"http"));
+    }
+
+    #[test]
+    fn sortable_columns_have_keys() {
+        let report = Report::new(&sample_records(), LocationLevel::Country).unwrap();
+        let html = render_to_string(&HtmlRenderer, &report).unwrap();
+
+        // The script sorts on the cell under the clicked header, so every such cell needs a key.
+        for table in html.split("<table class=\"sortable\">").skip(1) {
+            let (head, body) = table.split_once("</thead>").unwrap();
+            let columns: Vec<usize> = head.split("</th>").positions(|x| x.contains("<th class=\"sortable\"")).collect();
+            for row in body.split("</table>").next().unwrap().split("<tr").skip(1) {
+                let cells: Vec<&str> = row.split("<td").skip(1).collect();
+                assert!(columns.iter().all(|x| cells[*x].contains("data-sort=")), "{}", row);
+            }
+        }
+        assert!(SCRIPT.contains("th.cellIndex"));
+    }
+}
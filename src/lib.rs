@@ -1,7 +1,26 @@
+pub mod error;
 pub mod reader;
 pub mod builder;
+pub mod classifier;
+pub mod resolver;
+pub mod gazetteer;
+pub mod calendar;
+pub mod timestamp;
+pub mod timezone;
 pub mod calc;
+pub mod segment;
+pub mod stats;
+pub mod sla;
+pub mod diff;
+pub mod validate;
 pub mod formatter;
+pub mod render;
+pub mod html;
 pub mod writer;
+pub mod batch;
+pub mod cli;
 
 pub mod models;
+
+#[cfg(test)]
+mod fixtures;
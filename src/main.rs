@@ -1,36 +1,120 @@
-mod reader;
-mod builder;
-mod calc;
-mod formatter;
-mod writer;
+use std::process;
 
-mod models;
+use clap::Parser;
 
-use std::env;
-use std::process;
+use shiptracker::error::{Diagnostics, ShipError, EXIT_BATCH_FAILED, EXIT_USAGE};
+use shiptracker::writer::{convert_as, diff_as, evaluate_sla_as, explain_as, stats_as, validate_as, write_portfolio_as, write_report_as};
+use shiptracker::batch::{run_batch, write_summary};
+use shiptracker::gazetteer::Gazetteer;
+use shiptracker::validate::validate_shipments;
+use shiptracker::cli::{BatchArgs, Cli, Command, ConvertArgs, DiffArgs, ExplainArgs, ReportArgs, SlaArgs, StatsArgs, ValidateArgs};
+use shiptracker::models::report::OutputFormat;
+
+fn report(args: &ReportArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let options = args.options()?;
+    let format = args.output.format();
+    let shipments = args.input.read.load(&args.input.file, diagnostics)?;
+
+    let contents = match shipments.as_slice() {
+        [shipment] => write_report_as(&shipment.records, &options, format)?,
+        _ => write_portfolio_as(&shipments, &options, format)?,
+    };
+    args.output.write(&contents)
+}
+
+/// Builds leniently so that every bad line is listed along with the other problems found, then fails
+/// if there were errors, or warnings with `--deny-warnings`. The skipped lines are part of the listing,
+/// so they are taken out of the diagnostics rather than summarised again.
+fn validate(args: &ValidateArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let shipments = args.input.read.load(&args.input.file, diagnostics)?;
+    let validation = validate_shipments(&args.input.file, &shipments, diagnostics, &Gazetteer::default());
+    diagnostics.warnings.clear();
+
+    args.output.write(&validate_as(&validation, args.output.format())?)?;
+    match (validation.errors, validation.warnings) {
+        (0, 0) => Ok(()),
+        (0, warnings) if args.deny_warnings => Err(ShipError::Invalid(format!("{} warning(s) found.", warnings))),
+        (0, _) => Ok(()),
+        (errors, _) => Err(ShipError::Invalid(format!("{} error(s) found.", errors))),
+    }
+}
+
+fn convert(args: &ConvertArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let shipments = args.input.read.load(&args.input.file, diagnostics)?;
+    args.output.write(&convert_as(&shipments, args.output.format_or(OutputFormat::Json))?)
+}
+
+fn stats(args: &StatsArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let options = args.reporting.options()?;
+    let shipments = args.input.read.load(&args.input.file, diagnostics)?;
+    args.output.write(&stats_as(&shipments, &options, args.output.format())?)
+}
+
+fn diff(args: &DiffArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let options = args.reporting.options()?;
+    let before = args.read.load(&args.old, diagnostics)?;
+    let after = args.read.load(&args.new, diagnostics)?;
+    args.output.write(&diff_as(&before, &after, &options, args.output.format())?)
+}
+
+fn explain(args: &ExplainArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let shipments = args.input.read.load(&args.input.file, diagnostics)?;
+    args.output.write(&explain_as(&shipments, args.output.format())?)
+}
+
+/// Prints or writes the SLA evaluation, then fails with the number of breaches so the exit code can gate alerting.
+fn check_sla(args: &SlaArgs, diagnostics: &mut Diagnostics) -> Result<(), ShipError> {
+    let config = args.config()?;
+    let options = args.reporting.options()?;
+    let shipments = args.input.read.load(&args.input.file, diagnostics)?;
+
+    let (evaluation, contents) = evaluate_sla_as(&shipments, &config, &options, args.output.format())?;
+    args.output.write(&contents)?;
+    match evaluation.breached {
+        0 => Ok(()),
+        count => Err(ShipError::SlaBreached(count)),
+    }
+}
 
-use reader::reader;
-use builder::data_builder;
-use writer::generate_file_report;
-use writer::generate_report;
+fn batch(args: &BatchArgs) {
+    let options = args.options().unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        process::exit(EXIT_USAGE);
+    });
 
-use crate::models::input::Input;
+    let summary = run_batch(&options).unwrap_or_else(|err| {
+        eprintln!("Error writing reports: {}", err);
+        process::exit(err.exit_code());
+    });
+
+    print!("{}", write_summary(&summary));
+    if !summary.is_success() {
+        process::exit(EXIT_BATCH_FAILED);
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let input = Input::new(&args).unwrap_or_else(|err| {
-        println!("Error parsing input: {}", err);
-        process::exit(1);
+    let cli = Cli::try_parse().unwrap_or_else(|err| {
+        let _ = err.print();
+        process::exit(if err.use_stderr() { EXIT_USAGE } else { 0 });
     });
 
-    if input.tag.is_some() {
-        let filename = input.tag.clone().unwrap();
-        let raw_string_data = reader(input).unwrap();
-        let completed_records = data_builder(raw_string_data).unwrap();
-        generate_file_report(&completed_records, filename)
-    } else {
-        let raw_string_data = reader(input).unwrap();
-        let completed_records = data_builder(raw_string_data).unwrap();
-        generate_report(&completed_records)
+    let mut diagnostics = Diagnostics::new(cli.command.read_args().strict);
+
+    let result = match &cli.command {
+        Command::Report(args) => report(args, &mut diagnostics),
+        Command::Validate(args) => validate(args, &mut diagnostics),
+        Command::Convert(args) => convert(args, &mut diagnostics),
+        Command::Stats(args) => stats(args, &mut diagnostics),
+        Command::Diff(args) => diff(args, &mut diagnostics),
+        Command::Explain(args) => explain(args, &mut diagnostics),
+        Command::Sla(args) => check_sla(args, &mut diagnostics),
+        Command::Batch(args) => return batch(args),
+    };
+    eprint!("{}", diagnostics.summary());
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        process::exit(err.exit_code());
     }
 }
@@ -0,0 +1,120 @@
+use csv::StringRecord;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Column {
+    Name(String),
+    Index(usize),
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct ColumnMap {
+    pub timestamp: Option<Column>,
+    pub date: Option<Column>,
+    pub time: Option<Column>,
+    pub status: Option<Column>,
+    pub location: Option<Column>,
+    pub tracking: Option<Column>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ColumnIndices {
+    pub timestamp: Option<usize>,
+    pub date: Option<usize>,
+    pub time: Option<usize>,
+    pub status: usize,
+    pub location: Option<usize>,
+    pub tracking: Option<usize>,
+}
+
+impl Column {
+    pub fn new(spec: &str) -> Column {
+        match spec.trim().parse::<usize>() {
+            Ok(index) => Column::Index(index),
+            Err(..) => Column::Name(spec.trim().to_string()),
+        }
+    }
+
+    fn resolve(&self, headers: &StringRecord) -> Result<usize, String> {
+        match self {
+            Column::Index(index) if *index < headers.len() => Ok(*index),
+            Column::Index(index) => Err(format!("Column index {} is out of range.", index)),
+            Column::Name(name) => headers.iter()
+                .position(|x| x.trim().eq_ignore_ascii_case(name))
+                .ok_or(format!("No column named '{}'.", name)),
+        }
+    }
+}
+
+impl ColumnMap {
+    pub fn new(spec: &str) -> Result<ColumnMap, String> {
+        let mut map = ColumnMap::default();
+
+        for pair in spec.split(',').filter(|x| !x.trim().is_empty()) {
+            let (field, column) = pair.split_once('=')
+                .ok_or(format!("Column mappings look like 'field=column', got '{}'.", pair))?;
+            let column = Some(Column::new(column));
+
+            match field.trim() {
+                "timestamp" => map.timestamp = column,
+                "date" => map.date = column,
+                "time" => map.time = column,
+                "status" => map.status = column,
+                "location" => map.location = column,
+                "tracking" => map.tracking = column,
+                other => return Err(format!("Unknown column field '{}'.", other)),
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn resolve(&self, headers: &StringRecord) -> Result<ColumnIndices, String> {
+        let resolve = |column: &Option<Column>| column.as_ref().map(|x| x.resolve(headers)).transpose();
+
+        let status = self.status.as_ref()
+            .ok_or("A column mapping needs a 'status' column.")?
+            .resolve(headers)?;
+        let indices = ColumnIndices {
+            timestamp: resolve(&self.timestamp)?,
+            date: resolve(&self.date)?,
+            time: resolve(&self.time)?,
+            status,
+            location: resolve(&self.location)?,
+            tracking: resolve(&self.tracking)?,
+        };
+
+        if indices.timestamp.is_none() && (indices.date.is_none() || indices.time.is_none()) {
+            return Err("A column mapping needs a 'timestamp' column or both 'date' and 'time' columns.".to_string())
+        }
+
+        Ok(indices)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn good_input() {
+        let map = ColumnMap::new("timestamp=Scan Time,status=2").unwrap();
+        assert_eq!(map.timestamp, Some(Column::Name("Scan Time".to_string())));
+        assert_eq!(map.status, Some(Column::Index(2)));
+    }
+
+    #[test]
+    fn bad_field() {
+        assert!(ColumnMap::new("when=0").is_err());
+    }
+
+    #[test]
+    fn resolve_headers() {
+        let headers = StringRecord::from(vec!["Date", "Time", "Status"]);
+        let indices = ColumnMap::new("date=date,time=Time,status=2").unwrap()
+            .resolve(&headers)
+            .unwrap();
+        assert_eq!((indices.date, indices.time, indices.status), (Some(0), Some(1), 2));
+        assert!(ColumnMap::new("status=Status").unwrap().resolve(&headers).is_err());
+    }
+}
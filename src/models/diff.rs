@@ -0,0 +1,58 @@
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffStatus {
+    /// Only in the new input.
+    Added,
+    /// Only in the old input.
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+/// One report figure that moved, in minutes. A side is `None` when the figure doesn't exist there,
+/// e.g. a layover in a country the other input never visits.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FigureChange {
+    pub name: String,
+    pub before: Option<i64>,
+    pub after: Option<i64>,
+}
+
+/// A shipment matched by tracking number across two inputs. Scans are listed as timestamp and status.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ShipmentDiff {
+    pub tracking: Option<String>,
+    pub status: DiffStatus,
+    pub figures: Vec<FigureChange>,
+    pub added_scans: Vec<String>,
+    pub removed_scans: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ReportDiff {
+    pub shipments: Vec<ShipmentDiff>,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+}
+
+impl FigureChange {
+    pub fn delta(&self) -> Option<i64> {
+        Some(self.after? - self.before?)
+    }
+}
+
+impl ShipmentDiff {
+    pub fn key(&self) -> &str {
+        self.tracking.as_deref().unwrap_or("untracked")
+    }
+}
+
+impl ReportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added + self.removed + self.changed == 0
+    }
+}
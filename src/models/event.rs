@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::event_kind::EventKind;
+use crate::models::record::Record;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct EventLocation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facility: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Event {
+    pub timestamp: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<EventLocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<EventKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking: Option<String>,
+}
+
+impl Event {
+    /// A built record as an event the JSON reader takes back: the timestamp carries its offset, the
+    /// country is an alpha-2 code and the kind is kept. Unknown locations are left out.
+    pub fn from_record(record: &Record) -> Event {
+        let location = record.location.as_ref()
+            .filter(|x| !x.is_unknown())
+            .map(|x| EventLocation {
+                facility: x.facility.clone(),
+                city: x.city.clone(),
+                region: x.region.clone(),
+                postal_code: x.postal_code.clone(),
+                country: x.alpha2().map(str::to_string),
+            });
+
+        Event {
+            timestamp: record.datetime.to_rfc3339(),
+            status: record.status.clone(),
+            location,
+            kind: Some(record.kind),
+            tracking: record.tracking.clone(),
+        }
+    }
+}
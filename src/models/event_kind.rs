@@ -0,0 +1,67 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Created,
+    Pickup,
+    Departure,
+    Arrival,
+    Processed,
+    Customs,
+    CustomsHold,
+    CustomsCleared,
+    OutForDelivery,
+    DeliveryAttempt,
+    Delivered,
+    Exception,
+    Unknown,
+}
+
+impl EventKind {
+    pub fn new(name: &str) -> Option<EventKind> {
+        serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+    }
+
+    /// The snake_case name `new` takes, as used in JSON.
+    pub fn name(&self) -> String {
+        serde_json::to_value(self).ok()
+            .and_then(|x| x.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EventKind::Created => "Label created",
+            EventKind::Pickup => "Pickup",
+            EventKind::Departure => "Departure",
+            EventKind::Arrival => "Arrival",
+            EventKind::Processed => "Processed",
+            EventKind::Customs => "Customs",
+            EventKind::CustomsHold => "Customs hold",
+            EventKind::CustomsCleared => "Customs cleared",
+            EventKind::OutForDelivery => "Out for delivery",
+            EventKind::DeliveryAttempt => "Delivery attempt",
+            EventKind::Delivered => "Delivered",
+            EventKind::Exception => "Exception",
+            EventKind::Unknown => "Unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn from_name() {
+        assert_eq!(EventKind::new("customs_hold"), Some(EventKind::CustomsHold));
+        assert_eq!(EventKind::new("teleported"), None);
+        assert_eq!(EventKind::OutForDelivery.name(), "out_for_delivery");
+    }
+}
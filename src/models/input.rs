@@ -1,55 +1,46 @@
 use std::ffi::OsStr;
 use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 use std::path::Path;
 
+use crate::error::ShipError;
+use crate::models::columns::ColumnMap;
+use crate::timestamp::TimestampParser;
+
 pub struct Input {
     pub filename: String,
     pub ext: String,
-    pub file: File,
-    pub tag: Option<String>,
+    pub source: Box<dyn BufRead>,
+    pub columns: Option<ColumnMap>,
+    pub time_format: Option<TimestampParser>,
+    pub strict: bool,
 }
 
 impl Input {
-    pub fn new(args: &[String]) -> Result<Input, &str> {
-        if args.len() > 3 {
-            return Err("Usage: shiptracker <FILE> <OP:FILENAME>\n\nInvalid Args: Only include the filename to be executed and an optional output filename with extension.")
-        }
-        if args.len() == 2 {
-            let filename = Self::parse_args_noout(args);
-            let raw_ext = Self::extract_file_extension(&filename);
-            if raw_ext.is_none() {
-                return Err("No file extension detected.")
-            }
-
-            let ext = raw_ext.unwrap().to_string();
-            let file = Self::parse_file(&filename).unwrap();
-
-            Ok(Input { filename, ext, file, tag: None })
+    /// Opens a file, or stdin for `-`, with every option left at its default. The format comes from
+    /// the extension unless one is given; stdin always needs one.
+    pub fn open(filename: &str, format: Option<&str>) -> Result<Input, ShipError> {
+        if filename == "-" {
+            let ext = format.ok_or_else(|| ShipError::Config("Reading from stdin requires '--input-format txt|csv|json'.".to_string()))?;
+            return Ok(Self::from_reader(Box::new(BufReader::new(io::stdin())), ext));
         }
 
-        else {
-            let (filename, flag) = Self::parse_args_stout(args);
-            let raw_ext = Self::extract_file_extension(&filename);
-            if raw_ext.is_none() {
-                return Err("No file extension detected.")
-            }
-
-            let tag = flag;
-            let ext = raw_ext.unwrap().to_string();
-            let file = Self::parse_file(&filename).unwrap();
+        let ext = match format {
+            Some(format) => format.to_string(),
+            None => Self::extract_file_extension(filename)
+                .ok_or_else(|| ShipError::Config("No file extension detected.".to_string()))?
+                .to_string(),
+        };
+        let file = Self::parse_file(filename).map_err(|err| ShipError::from(err).in_file(filename))?;
 
-            Ok(Input { filename, ext, file, tag: Some(tag) })
-        }
-    }
+        let mut input = Self::from_reader(Box::new(BufReader::new(file)), &ext);
+        input.filename = filename.to_string();
 
-    fn parse_args_noout(args: &[String]) -> String {
-        args[1].clone()
+        Ok(input)
     }
 
-    fn parse_args_stout(args: &[String]) -> (String, String) {
-        let file = args[1].clone();
-        let flag = args[2].clone();
-        (file, flag)
+    pub fn from_reader(source: Box<dyn BufRead>, format: &str) -> Input {
+        Input { filename: "-".to_string(), ext: format.to_string(), source, columns: None, time_format: None, strict: false }
     }
 
     fn extract_file_extension(filename: &str) -> Option<&str> {
@@ -71,22 +62,26 @@ mod tests {
 
     #[test]
     fn good_input() {
-        assert!(Input::new(&["target/debug/shiptracker".to_string(),
-                           "tests/data/test_data.csv".to_string()]).is_ok());
+        assert!(Input::open("tests/data/test_data.csv", None).is_ok());
     }
 
     #[test]
     #[should_panic]
     fn bad_input() {
-        Input::new(&["some path".to_string(), "some_file.txt".to_string()]).unwrap();
+        Input::open("some_file.txt", None).unwrap();
+    }
+
+    #[test]
+    fn stdin_requires_format() {
+        assert!(Input::open("-", None).is_err());
+        assert_eq!(Input::open("-", Some("json")).unwrap().ext, "json");
     }
 
-    // #[test]
-    // fn try_args() {
-    //     assert_eq!("tests/data/test_data.csv".to_string(),
-    //                Input::parse_args(&["target/debug/shiptracker".to_string(),
-    //                                  "tests/data/test_data.csv".to_string()]))
-    // }
+    #[test]
+    fn format_overrides_extension() {
+        assert_eq!(Input::open("tests/data/test_data.txt", Some("csv")).unwrap().ext, "csv");
+        assert!(Input::open("tests/data/config", None).is_err());
+    }
 
     #[test]
     fn try_ext() {
@@ -0,0 +1,266 @@
+use std::fmt;
+use celes::Country;
+use itertools::Itertools;
+use serde::Serialize;
+
+/// How reports name a location nothing is known about.
+pub const UNKNOWN_LOCATION: &str = "Unknown";
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationLevel {
+    Facility,
+    City,
+    Region,
+    Country,
+}
+
+/// Which resolution rule turned a token into the location's country.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchRule {
+    UserAlias,
+    IsoAlpha2,
+    IsoAlpha3,
+    CountryName,
+    /// The embedded dictionary of native-language names and short forms such as "UK".
+    Alias,
+    /// A US state or Canadian province code or name.
+    Subdivision,
+    /// A city or hub name from the gazetteer.
+    Gazetteer,
+    /// An IATA airport code from the gazetteer.
+    Iata,
+    /// A misspelt gazetteer name.
+    Fuzzy,
+    /// Nothing on the scan or its neighbours named a country.
+    Unknown,
+}
+
+/// How a location's country was arrived at: the text it was read from, the rule that matched and how
+/// far to trust it. Countries copied from a neighbouring scan say so, and where from.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Provenance {
+    pub token: Option<String>,
+    pub rule: MatchRule,
+    /// From 0 (nothing known) to 1.
+    pub confidence: f64,
+    pub inherited: bool,
+    /// The input line of the scan it was copied from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inherited_from: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
+pub struct Location {
+    pub facility: Option<String>,
+    pub city: Option<String>,
+    pub region: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<Country>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Coordinates>,
+}
+
+impl LocationLevel {
+    pub fn new(name: &str) -> Option<LocationLevel> {
+        match name {
+            "facility" => Some(LocationLevel::Facility),
+            "city" => Some(LocationLevel::City),
+            "region" => Some(LocationLevel::Region),
+            "country" => Some(LocationLevel::Country),
+            _ => None,
+        }
+    }
+}
+
+impl MatchRule {
+    /// How far a country found by this rule is trusted, before any discount for inheriting it.
+    pub fn confidence(&self) -> f64 {
+        match self {
+            MatchRule::IsoAlpha3 | MatchRule::CountryName | MatchRule::UserAlias => 0.95,
+            MatchRule::IsoAlpha2 | MatchRule::Alias => 0.9,
+            MatchRule::Iata => 0.85,
+            MatchRule::Subdivision | MatchRule::Gazetteer => 0.8,
+            MatchRule::Fuzzy => 0.5,
+            MatchRule::Unknown => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for MatchRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MatchRule::UserAlias => "user alias",
+            MatchRule::IsoAlpha2 => "ISO alpha-2 code",
+            MatchRule::IsoAlpha3 => "ISO alpha-3 code",
+            MatchRule::CountryName => "country name",
+            MatchRule::Alias => "country alias",
+            MatchRule::Subdivision => "state/province",
+            MatchRule::Gazetteer => "gazetteer",
+            MatchRule::Iata => "IATA code",
+            MatchRule::Fuzzy => "gazetteer, fuzzy",
+            MatchRule::Unknown => "no match",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Provenance {
+    pub fn new(rule: MatchRule, token: &str) -> Provenance {
+        Provenance { token: Some(token.to_string()), rule, confidence: rule.confidence(), inherited: false, inherited_from: None }
+    }
+
+    pub fn unknown() -> Provenance {
+        Provenance { token: None, rule: MatchRule::Unknown, confidence: 0.0, inherited: false, inherited_from: None }
+    }
+
+    /// The same match seen from a scan that copied it. A copied country is only a guess, so it is
+    /// trusted half as much.
+    pub fn inherit(&self, line: Option<usize>) -> Provenance {
+        Provenance { confidence: self.confidence / 2.0, inherited: true, inherited_from: line, ..self.clone() }
+    }
+}
+
+impl Coordinates {
+    /// Great-circle distance in kilometres.
+    pub fn distance_km(&self, other: &Coordinates) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+
+        2.0 * 6371.0 * a.sqrt().asin()
+    }
+}
+
+impl Location {
+    pub fn from_country(country: Country) -> Location {
+        Location { country: Some(country), ..Location::default() }
+    }
+
+    /// A location with no parts at all, for records left unfilled. It is named `Unknown` at every level.
+    pub fn unknown() -> Location {
+        Location::default()
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        Location { provenance: None, ..self.clone() } == Location::default()
+    }
+
+    pub fn alpha2(&self) -> Option<&'static str> {
+        self.country.as_ref().map(|x| x.alpha2)
+    }
+
+    pub fn alpha3(&self) -> Option<&'static str> {
+        self.country.as_ref().map(|x| x.alpha3)
+    }
+
+    /// The name used to group records at the given level. Regions are qualified by their country
+    /// code, as "OH" alone is ambiguous across countries.
+    pub fn name(&self, level: LocationLevel) -> Option<String> {
+        if self.is_unknown() {
+            return Some(UNKNOWN_LOCATION.to_string());
+        }
+
+        match level {
+            LocationLevel::Facility => self.facility.clone(),
+            LocationLevel::City => self.city.clone(),
+            LocationLevel::Region => self.region.as_ref().map(|region| match self.alpha2() {
+                Some(alpha2) => format!("{}, {}", region, alpha2),
+                None => region.clone(),
+            }),
+            LocationLevel::Country => self.country.as_ref().map(|x| x.long_name.to_string()),
+        }
+    }
+
+    /// Copies any component this location is missing from another one, without overwriting.
+    pub fn fill_from(&mut self, other: &Location) {
+        let fill = |x: &mut Option<String>, y: &Option<String>| if x.is_none() { *x = y.clone() };
+
+        fill(&mut self.facility, &other.facility);
+        fill(&mut self.city, &other.city);
+        fill(&mut self.region, &other.region);
+        fill(&mut self.postal_code, &other.postal_code);
+        if self.country.is_none() {
+            self.country = other.country;
+            self.provenance = other.provenance.clone();
+        }
+        if self.coordinates.is_none() {
+            self.coordinates = other.coordinates;
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let place = self.facility.as_ref().or(self.city.as_ref());
+        let country = self.country.as_ref().map(|x| x.long_name.to_string());
+        let parts: Vec<&String> = [place, self.region.as_ref(), country.as_ref()].into_iter()
+            .flatten()
+            .collect();
+
+        if parts.is_empty() {
+            return write!(f, "{}", UNKNOWN_LOCATION);
+        }
+
+        write!(f, "{}", parts.iter().join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn names_by_level() {
+        let location = Location {
+            facility: Some("CINCINNATI HUB".to_string()),
+            city: Some("CINCINNATI".to_string()),
+            region: Some("OH".to_string()),
+            postal_code: None,
+            country: Some(Country::the_united_states_of_america()),
+            provenance: None,
+            coordinates: None,
+        };
+        assert_eq!(location.name(LocationLevel::Facility), Some("CINCINNATI HUB".to_string()));
+        assert_eq!(location.name(LocationLevel::Region), Some("OH, US".to_string()));
+        assert_eq!(location.name(LocationLevel::Country), Some("The United States Of America".to_string()));
+        assert_eq!(location.alpha3(), Some("USA"));
+        assert_eq!(location.to_string(), "CINCINNATI HUB, OH, The United States Of America");
+    }
+
+    #[test]
+    fn fill_missing_parts() {
+        let mut location = Location { city: Some("LEIPZIG".to_string()), ..Location::default() };
+        location.fill_from(&Location::from_country(Country::germany()));
+        assert_eq!(location.city, Some("LEIPZIG".to_string()));
+        assert_eq!(location.alpha2(), Some("DE"));
+    }
+
+    #[test]
+    fn great_circle_distance() {
+        let cincinnati = Coordinates { latitude: 39.10, longitude: -84.51 };
+        let leipzig = Coordinates { latitude: 51.34, longitude: 12.37 };
+        assert_eq!(cincinnati.distance_km(&leipzig).round(), 7145.0);
+        assert_eq!(leipzig.distance_km(&leipzig), 0.0);
+    }
+
+    #[test]
+    fn unknown_at_every_level() {
+        let location = Location::unknown();
+        assert_eq!(location.name(LocationLevel::City), Some(UNKNOWN_LOCATION.to_string()));
+        assert_eq!(location.name(LocationLevel::Country), Some(UNKNOWN_LOCATION.to_string()));
+        assert_eq!(location.to_string(), UNKNOWN_LOCATION);
+        assert_eq!(Location::from_country(Country::canada()).name(LocationLevel::City), None);
+    }
+}
@@ -1,3 +1,15 @@
+pub mod columns;
+pub mod diff;
+pub mod event;
+pub mod event_kind;
 pub mod input;
+pub mod location;
+pub mod portfolio;
 pub mod record;
+pub mod report;
+pub mod shipment;
+pub mod sla;
+pub mod stats;
+pub mod stay;
 pub mod transfer;
+pub mod validation;
@@ -0,0 +1,154 @@
+use serde::Serialize;
+
+use crate::calc::find_event;
+use crate::models::event_kind::EventKind;
+use crate::models::report::{Report, ReportOptions, ShipmentState};
+use crate::models::shipment::Shipment;
+use crate::models::stats::PortfolioStats;
+use crate::stats::calc_portfolio_stats;
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ShipmentReport {
+    pub tracking: Option<String>,
+    pub report: Report,
+}
+
+/// A shipment that could not be reported on, e.g. one with a single scan.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FailedShipment {
+    pub tracking: Option<String>,
+    pub records: usize,
+    pub error: String,
+}
+
+/// Totals across every shipment in the input. Transit figures cover the reported shipments only.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PortfolioSummary {
+    pub shipments: usize,
+    pub reported: usize,
+    pub failed: usize,
+    pub records: usize,
+    pub delivered: usize,
+    /// Shipments with a single scan so far. They are left out of the transit figures.
+    pub awaiting: usize,
+    pub min_transit_minutes: Option<i64>,
+    pub mean_transit_minutes: Option<i64>,
+    pub max_transit_minutes: Option<i64>,
+    pub slowest: Option<String>,
+}
+
+/// One report per tracking number plus a summary across them.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Portfolio {
+    pub shipments: Vec<ShipmentReport>,
+    pub failed: Vec<FailedShipment>,
+    pub summary: PortfolioSummary,
+    pub stats: PortfolioStats,
+}
+
+impl ShipmentReport {
+    pub fn key(&self) -> &str {
+        self.tracking.as_deref().unwrap_or("untracked")
+    }
+}
+
+impl Portfolio {
+    pub fn new(shipments: &[Shipment], options: &ReportOptions) -> Portfolio {
+        let mut reports = Vec::new();
+        let mut failed = Vec::new();
+
+        for shipment in shipments {
+            match Report::with_options(&shipment.records, options) {
+                Ok(report) => reports.push(ShipmentReport { tracking: shipment.tracking.clone(), report }),
+                Err(err) => failed.push(FailedShipment {
+                    tracking: shipment.tracking.clone(),
+                    records: shipment.records.len(),
+                    error: err.to_string(),
+                }),
+            }
+        }
+
+        let summary = PortfolioSummary::new(shipments, &reports);
+        let stats = calc_portfolio_stats(&reports.iter().map(|x| &x.report).collect::<Vec<_>>());
+        Portfolio { shipments: reports, failed, summary, stats }
+    }
+}
+
+impl PortfolioSummary {
+    fn new(shipments: &[Shipment], reports: &[ShipmentReport]) -> PortfolioSummary {
+        let moving: Vec<&ShipmentReport> = reports.iter().filter(|x| x.report.state != ShipmentState::AwaitingFirstMovement).collect();
+        let transits: Vec<i64> = moving.iter().map(|x| x.report.total_transit_minutes).collect();
+        let slowest = moving.iter()
+            .max_by_key(|x| x.report.total_transit_minutes)
+            .map(|x| x.key().to_string());
+        let mean = match transits.len() {
+            0 => None,
+            count => Some(transits.iter().sum::<i64>() / count as i64),
+        };
+
+        PortfolioSummary {
+            shipments: shipments.len(),
+            reported: reports.len(),
+            failed: shipments.len() - reports.len(),
+            records: shipments.iter().map(|x| x.records.len()).sum(),
+            delivered: shipments.iter().filter(|x| find_event(&x.records, EventKind::Delivered).is_some()).count(),
+            awaiting: reports.len() - moving.len(),
+            min_transit_minutes: transits.iter().min().copied(),
+            mean_transit_minutes: mean,
+            max_transit_minutes: transits.iter().max().copied(),
+            slowest,
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::fixtures::record_at;
+
+    fn shipment(tracking: &str, times: &[&str], delivered: bool) -> Shipment {
+        let records = times.iter()
+            .map(|datetime| {
+                let mut record = record_at(datetime, "Processed", None);
+                record.set_tracking(Some(tracking.to_string()));
+                record
+            })
+            .collect::<Vec<_>>();
+        let mut shipment = Shipment::new(Some(tracking.to_string()), records);
+        if delivered {
+            shipment.records.last_mut().unwrap().set_kind(EventKind::Delivered);
+        }
+        shipment
+    }
+
+    #[test]
+    fn summary_across_shipments() {
+        let shipments = vec![
+            shipment("A1", &["2017-01-20 10:00:00 +0000", "2017-01-20 12:00:00 +0000"], true),
+            shipment("B2", &["2017-01-20 10:00:00 +0000", "2017-01-21 10:00:00 +0000"], false),
+            shipment("C3", &["2017-01-20 10:00:00 +0000"], false),
+        ];
+        let portfolio = Portfolio::new(&shipments, &ReportOptions::default());
+
+        assert_eq!(portfolio.shipments.len(), 3);
+        assert!(portfolio.failed.is_empty());
+        assert_eq!(portfolio.summary.awaiting, 1);
+        assert_eq!(portfolio.summary.records, 5);
+        assert_eq!(portfolio.summary.delivered, 1);
+        assert_eq!(portfolio.summary.min_transit_minutes, Some(120));
+        assert_eq!(portfolio.summary.mean_transit_minutes, Some(780));
+        assert_eq!(portfolio.summary.slowest, Some("B2".to_string()));
+
+        let stats = &portfolio.stats;
+        assert_eq!(stats.overall.transit.as_ref().unwrap().median, 780);
+        assert_eq!(stats.lanes.len(), 1);
+        assert_eq!(stats.lanes[0].key, "Unknown -> Unknown");
+        assert_eq!(stats.weeks[0].key, "2017-W03");
+        assert_eq!(stats.weeks[0].stats.shipments, 3);
+        assert_eq!(stats.weeks[0].stats.transit.as_ref().unwrap().count, 2);
+    }
+}
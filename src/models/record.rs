@@ -1,21 +1,61 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Offset, TimeZone, Utc};
+use serde::Serialize;
 
-#[derive(Debug, PartialEq, Clone)]
+use crate::models::event_kind::EventKind;
+use crate::models::location::Location;
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Record {
-    pub datetime: NaiveDateTime,
+    pub datetime: DateTime<FixedOffset>,
     pub status: String,
-    pub locale: Option<String>,
+    pub location: Option<Location>,
+    pub tracking: Option<String>,
+    pub explicit_offset: bool,
+    pub kind: EventKind,
+    /// The 1-based input line the record was read from, or its event number in a JSON input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
 }
 
 impl Record {
+    /// Records start out in UTC until an offset is known, either from the input or from the locale.
     pub fn new(datetime: NaiveDateTime, status: String) -> Result<Record, String> {
-        let locale: Option<String> = None;
+        let datetime = Utc.fix().from_utc_datetime(&datetime);
+        let location: Option<Location> = None;
+        let tracking: Option<String> = None;
+
+        Ok(Record { datetime, status, location, tracking, explicit_offset: false, kind: EventKind::Unknown, line: None })
+    }
+
+    pub fn set_location(&mut self, new_location: Option<Location>) {
+        self.location = new_location
+    }
+
+    pub fn set_kind(&mut self, new_kind: EventKind) {
+        self.kind = new_kind
+    }
+
+    pub fn set_tracking(&mut self, new_tracking: Option<String>) {
+        self.tracking = new_tracking
+    }
 
-        Ok(Record { datetime, status, locale })
+    pub fn set_line(&mut self, new_line: Option<usize>) {
+        self.line = new_line
     }
 
-    pub fn set_locale(&mut self, new_locale: Option<String>) {
-        self.locale = new_locale
+    /// Keeps the local wall-clock time and reinterprets it in the given offset.
+    pub fn set_offset(&mut self, offset: FixedOffset, explicit: bool) {
+        let local = self.datetime.naive_local();
+        self.datetime = offset.from_local_datetime(&local).unwrap();
+        self.explicit_offset = explicit
+    }
+
+    pub fn local_datetime(&self) -> NaiveDateTime {
+        self.datetime.naive_local()
+    }
+
+    pub fn utc_datetime(&self) -> NaiveDateTime {
+        self.datetime.naive_utc()
     }
 }
 
@@ -29,4 +69,13 @@ mod test {
         let dt = NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap();
         assert!(Record::new(dt, "Package arrived at destination".to_string()).is_ok())
     }
+
+    #[test]
+    fn offset_keeps_local_time() {
+        let dt = NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap();
+        let mut record = Record::new(dt, "Package arrived at destination".to_string()).unwrap();
+        record.set_offset(FixedOffset::east_opt(3600).unwrap(), false);
+        assert_eq!(record.local_datetime(), dt);
+        assert_eq!(record.utc_datetime(), dt - chrono::Duration::hours(1));
+    }
 }
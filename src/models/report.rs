@@ -0,0 +1,294 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+
+use crate::calc::{calc_business_time_between, calc_layover_times_with, calc_longest_delay_with, calc_longest_transfers_with, calc_time_between, calc_total_business_time, calc_total_shipment_time, calc_transfers_with, find_event, sort_transfers_by_duration, MILESTONES};
+use crate::calendar::BusinessCalendar;
+use crate::error::ShipError;
+use crate::models::event_kind::EventKind;
+use crate::models::location::LocationLevel;
+use crate::models::record::Record;
+use crate::models::stay::{Dwell, Stay};
+use crate::models::transfer::Transfer;
+use crate::segment::segment_stays_with;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LegOrder {
+    Time,
+    Duration,
+}
+
+/// How far a shipment has got. With a single scan, usually a freshly created label, there are no legs
+/// to measure yet.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShipmentState {
+    AwaitingFirstMovement,
+    InTransit,
+    Delivered,
+}
+
+/// A part of the report that can be left out. Every section is shown unless `ReportOptions::sections`
+/// says otherwise; the data is still computed, only the output skips it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Section {
+    Total,
+    Layovers,
+    Stays,
+    Milestones,
+    Legs,
+    Longest,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReportOptions {
+    pub group_by: LocationLevel,
+    pub leg_order: LegOrder,
+    /// Show the N longest legs in place of the single longest delay.
+    pub top_legs: Option<usize>,
+    /// Report business time next to elapsed time for every duration.
+    pub calendar: Option<BusinessCalendar>,
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Milestone {
+    pub label: String,
+    pub from: EventKind,
+    pub to: EventKind,
+    pub minutes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub business_minutes: Option<i64>,
+}
+
+/// Everything the report shows, computed once. Durations are whole minutes and timestamps
+/// serialize as RFC 3339 with their local offset. Sections that need at least one leg are `None`
+/// (or empty) while the shipment is awaiting its first movement.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Report {
+    pub group_by: LocationLevel,
+    pub state: ShipmentState,
+    pub first_scan: DateTime<FixedOffset>,
+    pub last_scan: DateTime<FixedOffset>,
+    pub total_transit_minutes: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_business_minutes: Option<i64>,
+    pub layovers: Vec<Dwell>,
+    pub stays: Vec<Stay>,
+    pub milestones: Vec<Milestone>,
+    pub longest_delay: Option<Transfer>,
+    pub leg_order: LegOrder,
+    pub legs: Vec<Transfer>,
+    pub longest_legs: Option<Vec<Transfer>>,
+    /// Every scan, for renderers that draw the whole history. Left out of JSON, which already carries the stays.
+    #[serde(skip)]
+    pub records: Vec<Record>,
+    #[serde(skip)]
+    pub sections: Vec<Section>,
+}
+
+impl Section {
+    pub const ALL: [Section; 6] = [Section::Total, Section::Layovers, Section::Stays, Section::Milestones, Section::Legs, Section::Longest];
+
+    pub fn new(name: &str) -> Option<Section> {
+        match name {
+            "total" => Some(Section::Total),
+            "layovers" => Some(Section::Layovers),
+            "stays" => Some(Section::Stays),
+            "milestones" => Some(Section::Milestones),
+            "legs" => Some(Section::Legs),
+            "longest" => Some(Section::Longest),
+            _ => None,
+        }
+    }
+
+    /// The report's JSON keys that belong to this section.
+    pub fn keys(&self) -> &'static [&'static str] {
+        match self {
+            Section::Total => &["state", "first_scan", "last_scan", "total_transit_minutes", "total_business_minutes"],
+            Section::Layovers => &["layovers"],
+            Section::Stays => &["stays"],
+            Section::Milestones => &["milestones"],
+            Section::Legs => &["leg_order", "legs"],
+            Section::Longest => &["longest_delay", "longest_legs"],
+        }
+    }
+
+    /// Parses a comma-separated list such as `total,legs`.
+    pub fn parse_list(value: &str) -> Option<Vec<Section>> {
+        value.split(',').map(|x| Section::new(x.trim())).collect()
+    }
+}
+
+impl Report {
+    pub fn shows(&self, section: Section) -> bool {
+        self.sections.contains(&section)
+    }
+
+    /// The legs for the closing section: the top N when asked for, otherwise the single longest delay.
+    /// Empty while awaiting first movement.
+    pub fn longest(&self) -> Vec<Transfer> {
+        match (&self.longest_legs, &self.longest_delay) {
+            (Some(legs), _) => legs.clone(),
+            (None, Some(delay)) => vec![delay.clone()],
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn new(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" | "txt" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "md" | "markdown" => Some(OutputFormat::Markdown),
+            "html" | "htm" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// The file extension for reports in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+        }
+    }
+}
+
+impl LegOrder {
+    pub fn new(name: &str) -> Option<LegOrder> {
+        match name {
+            "time" => Some(LegOrder::Time),
+            "duration" => Some(LegOrder::Duration),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ReportOptions {
+    fn default() -> ReportOptions {
+        ReportOptions { group_by: LocationLevel::Country, leg_order: LegOrder::Time, top_legs: None, calendar: None, sections: Section::ALL.to_vec() }
+    }
+}
+
+impl Report {
+    pub fn new(records: &[Record], level: LocationLevel) -> Result<Report, ShipError> {
+        Report::with_options(records, &ReportOptions { group_by: level, ..ReportOptions::default() })
+    }
+
+    pub fn with_options(records: &[Record], options: &ReportOptions) -> Result<Report, ShipError> {
+        let level = options.group_by;
+        let calendar = options.calendar.as_ref();
+        let (first, last) = match (records.first(), records.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(ShipError::NoRecords),
+        };
+        let milestones = MILESTONES.iter()
+            .map(|(label, from, to)| Milestone {
+                label: label.to_string(),
+                from: *from,
+                to: *to,
+                minutes: calc_time_between(records, *from, *to),
+                business_minutes: calendar.and_then(|x| calc_business_time_between(records, *from, *to, x)),
+            })
+            .collect();
+        let legs = match options.leg_order {
+            LegOrder::Time => calc_transfers_with(records, calendar)?,
+            LegOrder::Duration => sort_transfers_by_duration(calc_transfers_with(records, calendar)?),
+        };
+        let longest_legs = match options.top_legs {
+            Some(count) => Some(calc_longest_transfers_with(records, count, calendar)?),
+            None => None,
+        };
+
+        let total_business_minutes = match calendar {
+            Some(calendar) => Some(calc_total_business_time(records, calendar)?),
+            None => None,
+        };
+
+        let state = match (records.len(), find_event(records, EventKind::Delivered)) {
+            (0..=1, _) => ShipmentState::AwaitingFirstMovement,
+            (_, Some(_)) => ShipmentState::Delivered,
+            (_, None) => ShipmentState::InTransit,
+        };
+
+        Ok(Report {
+            group_by: level,
+            state,
+            first_scan: first.datetime,
+            last_scan: last.datetime,
+            total_transit_minutes: calc_total_shipment_time(records)?,
+            total_business_minutes,
+            layovers: calc_layover_times_with(records, level, calendar)?,
+            stays: segment_stays_with(records, level, calendar),
+            milestones,
+            longest_delay: calc_longest_delay_with(records, calendar)?,
+            leg_order: options.leg_order,
+            legs,
+            longest_legs,
+            records: records.to_vec(),
+            sections: options.sections.clone(),
+        })
+    }
+}
+
+
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::fixtures::sample_records;
+
+    #[test]
+    fn report_as_json() {
+        let records = sample_records();
+        let report = Report::new(&records, LocationLevel::Country).unwrap();
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(json["total_transit_minutes"], 8332);
+        assert_eq!(json["first_scan"], "2017-01-20T09:12:44-05:00");
+        assert_eq!(json["longest_delay"]["minutes"], 2683);
+        assert_eq!(json["layovers"][0]["minutes"], 4729);
+        assert_eq!(json["legs"].as_array().unwrap().len(), records.len() - 1);
+    }
+
+    #[test]
+    fn top_legs_by_duration() {
+        let records = sample_records();
+        let options = ReportOptions { leg_order: LegOrder::Duration, top_legs: Some(3), ..ReportOptions::default() };
+        let report = Report::with_options(&records, &options).unwrap();
+
+        assert_eq!(Some(&report.legs[0]), report.longest_delay.as_ref());
+        assert!(report.legs.windows(2).all(|pair| pair[0].minutes >= pair[1].minutes));
+        assert_eq!(report.longest_legs.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn awaiting_first_movement() {
+        let records = sample_records();
+        let report = Report::new(&records[..1], LocationLevel::Country).unwrap();
+
+        assert_eq!(report.state, ShipmentState::AwaitingFirstMovement);
+        assert_eq!(report.total_transit_minutes, 0);
+        assert!(report.longest_delay.is_none());
+        assert!(report.legs.is_empty() && report.longest().is_empty());
+        assert_eq!(Report::new(&records, LocationLevel::Country).unwrap().state, ShipmentState::Delivered);
+        assert!(matches!(Report::new(&[], LocationLevel::Country), Err(ShipError::NoRecords)));
+    }
+}
@@ -0,0 +1,21 @@
+use serde::Serialize;
+
+use crate::models::record::Record;
+
+/// The records of one tracking number, in time order.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Shipment {
+    pub tracking: Option<String>,
+    pub records: Vec<Record>,
+}
+
+impl Shipment {
+    pub fn new(tracking: Option<String>, records: Vec<Record>) -> Shipment {
+        Shipment { tracking, records }
+    }
+
+    /// The tracking number, or "untracked" for records that carried none.
+    pub fn key(&self) -> &str {
+        self.tracking.as_deref().unwrap_or("untracked")
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlaStatus {
+    Met,
+    /// Not delivered yet, and still inside the limit.
+    Pending,
+    Breached,
+}
+
+/// The part of the journey that used up the most time: either one leg between scans or one stay in a location.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BreachCause {
+    Leg { from: String, to: String, minutes: i64 },
+    Locale { name: String, minutes: i64 },
+}
+
+/// One shipment measured against the first commitment that matches it. `margin_minutes` is the time
+/// left under the limit, negative by the amount of any breach.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct SlaResult {
+    pub tracking: Option<String>,
+    pub commitment: String,
+    pub lane: String,
+    pub limit_minutes: i64,
+    pub elapsed_minutes: i64,
+    pub margin_minutes: i64,
+    pub status: SlaStatus,
+    pub cause: Option<BreachCause>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct SlaEvaluation {
+    pub results: Vec<SlaResult>,
+    /// Shipments no commitment applies to, by tracking number.
+    pub unmatched: Vec<String>,
+    pub met: usize,
+    pub pending: usize,
+    pub breached: usize,
+}
+
+impl SlaResult {
+    pub fn key(&self) -> &str {
+        self.tracking.as_deref().unwrap_or("untracked")
+    }
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+/// Spread of one figure across shipments, in whole minutes. Percentiles use the nearest-rank method,
+/// so every value is one that was actually observed, except the median of an even count.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: i64,
+    pub median: i64,
+    pub p90: i64,
+    pub p95: i64,
+}
+
+/// Layover in one country across the shipments that passed through it.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct CountryLayover {
+    pub country: String,
+    pub minutes: Distribution,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct ShipmentStats {
+    pub shipments: usize,
+    pub transit: Option<Distribution>,
+    pub longest_leg: Option<Distribution>,
+    pub layovers: Vec<CountryLayover>,
+}
+
+/// Statistics for the shipments sharing a lane (`origin -> destination` country) or a ship week (`2017-W03`).
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct GroupStats {
+    pub key: String,
+    pub stats: ShipmentStats,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PortfolioStats {
+    pub overall: ShipmentStats,
+    pub lanes: Vec<GroupStats>,
+    pub weeks: Vec<GroupStats>,
+}
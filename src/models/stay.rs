@@ -0,0 +1,31 @@
+use serde::Serialize;
+
+use crate::models::record::Record;
+
+/// One consecutive visit to a location, from the first scan there to the last one before the
+/// shipment moved on.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Stay {
+    pub name: String,
+    pub arrival: Record,
+    pub departure: Record,
+    pub minutes: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub business_minutes: Option<i64>,
+}
+
+/// Dwell summed over every visit to a location. Time spent elsewhere between visits is not counted.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Dwell {
+    pub name: String,
+    pub visits: usize,
+    pub minutes: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub business_minutes: Option<i64>,
+}
+
+impl Stay {
+    pub fn new(name: String, arrival: Record, departure: Record, minutes: i64) -> Stay {
+        Stay { name, arrival, departure, minutes, business_minutes: None }
+    }
+}
@@ -1,17 +1,21 @@
 use std::error::Error;
+use serde::Serialize;
 
 use crate::models::record::Record;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Transfer {
     pub from: Record,
     pub to: Record,
     pub minutes: i64,
+    /// Working minutes under the report's business calendar, when one is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub business_minutes: Option<i64>,
 }
 
 impl Transfer {
     pub fn new(from: Record, to: Record, minutes: i64) -> Result<Transfer, Box<dyn Error>> {
-        Ok(Transfer { from, to, minutes})
+        Ok(Transfer { from, to, minutes, business_minutes: None })
     }
 }
 
@@ -21,21 +25,15 @@ mod test {
     use super::*;
 
     use celes::Country;
-    use chrono::NaiveDateTime;
+
+    use crate::fixtures::record_at;
+    use crate::models::location::Location;
 
     #[test]
     fn good_input() {
-        let recs = vec![
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-23 16:02:24", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Departed Facility in CINCINNATI HUB,OH-USA".to_string(),
-                locale: Some(Country::the_united_states_of_america().to_string()),
-            },
-            Record {
-                datetime: NaiveDateTime::parse_from_str("2017-01-24 18:10:36", "%Y-%m-%d %H:%M:%S").unwrap(),
-                status: "Customs status updated;".to_string(),
-                locale: None,
-            },
+        let recs = [
+            record_at("2017-01-23 16:02:24 +0000", "Departed Facility in CINCINNATI HUB,OH-USA", Some(Location::from_country(Country::the_united_states_of_america()))),
+            record_at("2017-01-24 18:10:36 +0000", "Customs status updated;", None),
         ];
         assert!(Transfer::new(recs[0].clone(), recs[1].clone(), 69).is_ok());
     }
@@ -0,0 +1,76 @@
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A line the reader or builder had to skip.
+    Unparseable,
+    /// The same timestamp and status as an earlier line.
+    Duplicate,
+    /// A timestamp earlier than the line before it, as written.
+    OutOfOrder,
+    UnknownLocation,
+    /// A change of country faster than any freight flight could make it.
+    ImpossibleJump,
+    /// The last scan of a shipment isn't a delivery.
+    MissingDelivery,
+    /// A later line that happens before the one above it once time zones are applied.
+    NegativeLeg,
+    ZeroLengthLeg,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem with an input. `line` is the input line, or the event number for JSON input, and is
+/// `None` for problems without a line of their own.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub tracking: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Validation {
+    pub file: String,
+    pub records: usize,
+    pub shipments: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl IssueKind {
+    /// The snake_case name used in JSON output, such as `out_of_order`.
+    pub fn name(&self) -> String {
+        serde_json::to_value(self).ok()
+            .and_then(|x| x.as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::Unparseable | IssueKind::ImpossibleJump | IssueKind::NegativeLeg => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl Issue {
+    pub fn new(kind: IssueKind, line: Option<usize>, tracking: Option<String>, message: String) -> Issue {
+        Issue { kind, severity: kind.severity(), line, tracking, message }
+    }
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.errors == 0
+    }
+}
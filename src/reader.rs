@@ -1,61 +1,189 @@
-use std::path::Path;
-use std::fs::File;
-use std::error::Error;
-use std::io::{BufRead, BufReader};
-use csv::Reader;
+use std::io::{BufRead, Read};
+use csv::{Reader, StringRecord};
 
+use crate::builder::{build_record, parse_location_field, parse_timestamp, BuildOptions};
+use crate::error::{Diagnostics, ShipError};
+use crate::models::columns::{ColumnIndices, ColumnMap};
+use crate::models::event::{Event, EventLocation};
+use crate::models::event_kind::EventKind;
 use crate::models::input::Input;
+use crate::models::location::Location;
+use crate::models::record::Record;
+use crate::timestamp::TimestampParser;
 
-fn build_csv_buffer_reader(filename: &str) -> Result<Reader<File>, Box<dyn Error>> {
-    let path = Path::new(filename);
-    Ok(Reader::from_path(path).unwrap())
+/// Raw lines keep their 1-based line number in the input so later stages can point back at them.
+pub enum RawData {
+    Lines(Vec<(usize, String)>),
+    Records(Vec<Record>),
 }
 
-fn parse_raw_csv_data(mut buf: Reader<File>) -> Result<Vec<String>, String> {
-    let data: Vec<String> = buf.records()
-        .map(|x| x.unwrap_or_else(|_| Err("!!Error parsing line!!").unwrap())
-                    .as_slice()
-                    .to_owned())
-        .collect();
+fn build_csv_buffer_reader<R: Read>(source: R) -> Result<Reader<R>, ShipError> {
+    Ok(Reader::from_reader(source))
+}
+
+fn csv_line(err: &csv::Error) -> Option<usize> {
+    err.position().map(|x| x.line() as usize)
+}
+
+fn parse_raw_csv_data<R: Read>(mut buf: Reader<R>, diagnostics: &mut Diagnostics) -> Result<Vec<(usize, String)>, ShipError> {
+    let mut lines = Vec::new();
 
-    Ok(data)
+    for row in buf.records() {
+        match row {
+            Ok(row) => {
+                let line = row.position().map(|x| x.line() as usize).unwrap_or(0);
+                lines.push((line, row.as_slice().to_owned()));
+            },
+            Err(err) => match csv_line(&err) {
+                Some(line) => diagnostics.report(ShipError::from(err).at_line(line))?,
+                None => return Err(err.into()),
+            },
+        }
+    }
+
+    Ok(lines)
 }
 
-fn build_text_buffer_reader(file: File) -> Result<BufReader<File>, Box<dyn Error>> {
-    Ok(BufReader::new(file))
+fn cell(row: &StringRecord, index: usize) -> &str {
+    row.get(index).unwrap_or("").trim()
 }
 
-fn parse_raw_text_data(buf: BufReader<File>) -> Result<Vec<String>, String> {
-    let data: Vec<String> = buf.lines()
-        .skip(1)
-        .filter_map(|x| x.unwrap_or_else(|_| Err("!!Error parsing line!!").unwrap())
-                    .parse::<String>()
-                    .ok())
-        .collect();
+fn build_mapped_record(row: &StringRecord, columns: &ColumnIndices, parser: Option<&TimestampParser>, options: &BuildOptions) -> Result<Record, ShipError> {
+    let timestamp = match (columns.timestamp, columns.date, columns.time) {
+        (Some(index), _, _) => cell(row, index).to_string(),
+        (None, Some(date), Some(time)) => format!("{} {}", cell(row, date), cell(row, time)),
+        _ => return Err(ShipError::Config("A column mapping needs a 'timestamp' column or both 'date' and 'time' columns.".to_string())),
+    };
 
-    Ok(data)
+    let timestamp = parse_timestamp(&timestamp, parser)?;
+    let mut record = build_record(timestamp, cell(row, columns.status).to_string())?;
+    record.set_location(columns.location.and_then(|index| parse_location_field(cell(row, index), options)));
+    record.set_tracking(columns.tracking
+                        .map(|index| cell(row, index).to_string())
+                        .filter(|x| !x.is_empty()));
+
+    Ok(record)
 }
 
-pub fn reader(inp: Input) -> Result<Vec<String>, String> {
-    match inp.ext.as_str() {
-        "txt" => {
-            let buffer_reader = build_text_buffer_reader(inp.file).unwrap();
-            let string_data = parse_raw_text_data(buffer_reader)
-                .unwrap_or_else(|err| Err(format!("Error parsing data from: {} - {}", inp.filename, err))
-                                .unwrap());
+fn parse_mapped_csv_data<R: Read>(mut buf: Reader<R>, map: &ColumnMap, parser: Option<&TimestampParser>, options: &BuildOptions, diagnostics: &mut Diagnostics) -> Result<Vec<Record>, ShipError> {
+    let headers = buf.headers()?.clone();
+    let columns = map.resolve(&headers).map_err(ShipError::Config)?;
+    let mut records = Vec::new();
 
-            Ok(string_data)
-        },
-        "csv" => {
-            let buffer_reader = build_csv_buffer_reader(&inp.filename).unwrap();
-            let string_data = parse_raw_csv_data(buffer_reader)
-                .unwrap_or_else(|err| Err(format!("Error parsing data from: {} - {}", inp.filename, err))
-                                .unwrap());
+    for row in buf.records() {
+        let row = match row {
+            Ok(row) => row,
+            Err(err) => match csv_line(&err) {
+                Some(line) => {
+                    diagnostics.report(ShipError::from(err).at_line(line))?;
+                    continue;
+                },
+                None => return Err(err.into()),
+            },
+        };
 
-            Ok(string_data)
-        },
-        _ => Err("!!Shiptracker can only parse '.txt' & '.csv' files!!".to_string())
+        match build_mapped_record(&row, &columns, parser, options) {
+            Ok(mut record) => {
+                record.set_line(row.position().map(|x| x.line() as usize));
+                records.push(record);
+            },
+            Err(err @ ShipError::Config(..)) => return Err(err),
+            Err(err) => {
+                let line = row.position().map(|x| x.line() as usize).unwrap_or(0);
+                diagnostics.report(err.at_line(line))?;
+            },
+        }
+    }
+
+    Ok(records)
+}
+
+fn parse_raw_text_data<R: BufRead>(buf: R) -> Result<Vec<(usize, String)>, ShipError> {
+    let mut lines = Vec::new();
+
+    for (index, line) in buf.lines().enumerate().skip(1) {
+        let line = line.map_err(|err| ShipError::from(err).at_line(index + 1))?;
+        if !line.trim().is_empty() {
+            lines.push((index + 1, line));
+        }
+    }
+
+    Ok(lines)
+}
+
+/// An event's location as given; the country is resolved from a code, a name or one of the user's aliases.
+fn event_location(location: &EventLocation, options: &BuildOptions) -> Location {
+    let resolved = location.country.as_ref().and_then(|x| parse_location_field(x, options));
+    let (country, provenance) = match resolved {
+        Some(resolved) => (resolved.country, resolved.provenance),
+        None => (None, None),
+    };
+
+    Location {
+        facility: location.facility.clone(),
+        city: location.city.clone(),
+        region: location.region.clone(),
+        postal_code: location.postal_code.clone(),
+        country,
+        provenance,
+        coordinates: None,
+    }
+}
+
+fn build_event_record(event: &Event, parser: Option<&TimestampParser>, options: &BuildOptions) -> Result<Record, ShipError> {
+    let timestamp = parse_timestamp(&event.timestamp, parser)?;
+    let mut record = build_record(timestamp, event.status.clone())?;
+    record.set_location(event.location.as_ref().map(|x| event_location(x, options)));
+    record.set_kind(event.kind.unwrap_or(EventKind::Unknown));
+    record.set_tracking(event.tracking.clone());
+
+    Ok(record)
+}
+
+/// JSON events have no useful line numbers once parsed, so problems are reported by event number.
+fn parse_raw_json_data<R: BufRead>(buf: R, parser: Option<&TimestampParser>, options: &BuildOptions, diagnostics: &mut Diagnostics) -> Result<Vec<Record>, ShipError> {
+    let events: Vec<Event> = serde_json::from_reader(buf)?;
+    let mut records = Vec::new();
+
+    for (index, event) in events.iter().enumerate() {
+        match build_event_record(event, parser, options) {
+            Ok(mut record) => {
+                record.set_line(Some(index + 1));
+                records.push(record);
+            },
+            Err(err) => diagnostics.report(ShipError::Invalid(format!("event {}: {}", index + 1, err)))?,
+        }
     }
+
+    Ok(records)
+}
+
+/// Reads an input strictly: the first bad line fails the whole read.
+pub fn reader(inp: Input) -> Result<RawData, ShipError> {
+    reader_with(inp, &BuildOptions::default(), &mut Diagnostics::new(true))
+}
+
+/// Location columns and event countries are resolved here, so they go through the options' aliases.
+pub fn reader_with(inp: Input, options: &BuildOptions, diagnostics: &mut Diagnostics) -> Result<RawData, ShipError> {
+    let filename = inp.filename.clone();
+    diagnostics.file = Some(filename.clone());
+
+    let raw_data = match (inp.ext.as_str(), &inp.columns) {
+        ("txt", _) => RawData::Lines(parse_raw_text_data(inp.source)?),
+        ("csv", Some(columns)) => {
+            let buffer_reader = build_csv_buffer_reader(inp.source)?;
+            let records = parse_mapped_csv_data(buffer_reader, columns, inp.time_format.as_ref(), options, diagnostics)?;
+            RawData::Records(records)
+        },
+        ("csv", None) => {
+            let buffer_reader = build_csv_buffer_reader(inp.source)?;
+            RawData::Lines(parse_raw_csv_data(buffer_reader, diagnostics)?)
+        },
+        ("json", _) => RawData::Records(parse_raw_json_data(inp.source, inp.time_format.as_ref(), options, diagnostics)?),
+        _ => return Err(ShipError::Config("!!Shiptracker can only parse '.txt', '.csv' & '.json' files!!".to_string())),
+    };
+
+    Ok(raw_data)
 }
 
 
@@ -65,14 +193,117 @@ mod tests {
 
     use super::*;
 
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+    use std::path::Path;
+
+    use crate::models::location::MatchRule;
+    use crate::resolver::CountryResolver;
+
     #[test]
-    fn build_text_buffer() {
-        let file = File::open("tests/data/test_data.txt").unwrap();
-        assert!(build_text_buffer_reader(file).is_ok());
+    fn parse_text_from_any_source() {
+        let source = Cursor::new("Date, Time, Status\n2017-01-23, 16:02:24, Departed Facility in CINCINNATI HUB,OH-USA\n");
+        let lines = parse_raw_text_data(source).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, 2);
     }
 
     #[test]
     fn build_csv_buffer() {
-        assert!(build_csv_buffer_reader("tests/data/test_data.csv").is_ok());
+        let file = File::open("tests/data/test_data.csv").unwrap();
+        assert!(build_csv_buffer_reader(file).is_ok());
+    }
+
+    #[test]
+    fn parse_mapped_csv() {
+        let source = Cursor::new("Scanned,Event,Where\n2017-01-23 16:02:24,Arrived 3 times at hub,DE\n");
+        let map = ColumnMap::new("timestamp=Scanned,status=Event,location=2").unwrap();
+        let records = parse_mapped_csv_data(build_csv_buffer_reader(source).unwrap(), &map, None, &BuildOptions::default(), &mut Diagnostics::default()).unwrap();
+        assert_eq!(records[0].status, "Arrived 3 times at hub");
+        assert_eq!(records[0].location.as_ref().unwrap().country, Some(celes::Country::germany()));
+    }
+
+    #[test]
+    fn parse_json_records() {
+        let file = File::open("tests/data/test_data.json").unwrap();
+        let records = parse_raw_json_data(BufReader::new(file), None, &BuildOptions::default(), &mut Diagnostics::default()).unwrap();
+        assert_eq!(records.len(), 6);
+        assert!(records[0].location.is_some());
+    }
+
+    fn event(json: &str) -> Event {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn event_with_location() {
+        let event = event(r#"{
+            "timestamp": "2017-01-23T16:02:24",
+            "status": "Departed Facility in CINCINNATI HUB",
+            "location": { "city": "CINCINNATI", "region": "OH", "country": "US" }
+        }"#);
+        let record = build_event_record(&event, None, &BuildOptions::default()).unwrap();
+        let location = record.location.unwrap();
+        assert_eq!(location.country, Some(celes::Country::the_united_states_of_america()));
+        assert_eq!(location.city, Some("CINCINNATI".to_string()));
+    }
+
+    #[test]
+    fn event_country_from_user_alias() {
+        let event = event(r#"{
+            "timestamp": "2017-01-23T16:02:24",
+            "status": "Arrived at Sort Facility",
+            "location": { "city": "MUNICH", "country": "Bayern" }
+        }"#);
+        assert_eq!(build_event_record(&event, None, &BuildOptions::default()).unwrap().location.unwrap().country, None);
+
+        let options = BuildOptions { resolver: CountryResolver::from_file(Path::new("tests/data/config/aliases.json")).unwrap(), ..BuildOptions::default() };
+        let location = build_event_record(&event, None, &options).unwrap().location.unwrap();
+        assert_eq!(location.country, Some(celes::Country::germany()));
+        assert_eq!(location.provenance.unwrap().rule, MatchRule::UserAlias);
+    }
+
+    #[test]
+    fn event_without_location() {
+        let event = event(r#"{
+            "timestamp": "2017-01-24 18:10:36",
+            "status": "Customs status updated;",
+            "kind": "customs_hold"
+        }"#);
+        let record = build_event_record(&event, None, &BuildOptions::default()).unwrap();
+        assert_eq!(record.location, None);
+        assert_eq!(record.kind, EventKind::CustomsHold);
+    }
+
+    #[test]
+    fn event_round_trip() {
+        let event = event(r#"{
+            "timestamp": "2017-01-23T16:02:24-05:00",
+            "status": "Departed Facility in CINCINNATI HUB",
+            "location": { "city": "CINCINNATI", "region": "OH", "country": "US" },
+            "kind": "departure"
+        }"#);
+        let record = build_event_record(&event, None, &BuildOptions::default()).unwrap();
+        assert_eq!(Event::from_record(&record), event);
+    }
+
+    #[test]
+    fn skip_bad_mapped_rows() {
+        let source = Cursor::new("Scanned,Event\n2017-01-23 16:02:24,Arrived at hub\nyesterday,Departed hub\n");
+        let map = ColumnMap::new("timestamp=Scanned,status=Event").unwrap();
+        let mut diagnostics = Diagnostics::default();
+        let records = parse_mapped_csv_data(build_csv_buffer_reader(source).unwrap(), &map, None, &BuildOptions::default(), &mut diagnostics).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(diagnostics.warnings[0].line(), Some(3));
+    }
+
+    #[test]
+    fn read_from_boxed_source() {
+        let source = Box::new(Cursor::new("Date,Time,Status\n2017-01-23,16:02:24,Processed at CINCINNATI HUB\n"));
+        let inp = Input::from_reader(source, "csv");
+        match reader(inp).unwrap() {
+            RawData::Lines(lines) => assert_eq!(lines.len(), 1),
+            RawData::Records(..) => panic!("csv input should produce raw lines"),
+        }
     }
 }
@@ -0,0 +1,362 @@
+use std::io::Write;
+
+use serde_json::Value;
+
+use crate::error::ShipError;
+use crate::formatter::{format_duration, format_layover_times, format_portfolio_stats, format_portfolio_summary, format_legs, format_location, format_longest_delay, format_longest_legs, format_milestones, format_state, format_stays, format_total_shipment_time};
+use crate::html::HtmlRenderer;
+use crate::models::portfolio::Portfolio;
+use crate::models::report::{LegOrder, OutputFormat, Report, Section, ShipmentState};
+
+/// Turns a computed report into one output format. Implement this to add a format of your own;
+/// the built-in ones are picked with `renderer_for`.
+pub trait ReportRenderer {
+    fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError>;
+
+    /// Renders every shipment of a multi-shipment input under its tracking number, then the summary.
+    fn render_portfolio(&self, portfolio: &Portfolio, out: &mut dyn Write) -> Result<(), ShipError> {
+        for shipment in &portfolio.shipments {
+            writeln!(out, "==== {} ====", shipment.key())?;
+            self.render(&shipment.report, out)?;
+        }
+        write!(out, "==== Summary ====\n{}", format_portfolio_summary(portfolio))?;
+        write!(out, "==== Statistics ====\n{}", format_portfolio_stats(&portfolio.stats))?;
+
+        Ok(())
+    }
+}
+
+/// The human-readable `[hh:mm]` layout.
+pub struct TextRenderer;
+
+pub struct JsonRenderer;
+
+/// One row per figure: `section,name,from,to,minutes`. Timestamps are RFC 3339 and missing values are empty.
+/// With top-N legs set there is one `longest_delay` row per leg.
+pub struct CsvRenderer;
+
+pub struct MarkdownRenderer;
+
+pub fn renderer_for(format: OutputFormat) -> Box<dyn ReportRenderer> {
+    match format {
+        OutputFormat::Text => Box::new(TextRenderer),
+        OutputFormat::Json => Box::new(JsonRenderer),
+        OutputFormat::Csv => Box::new(CsvRenderer),
+        OutputFormat::Markdown => Box::new(MarkdownRenderer),
+        OutputFormat::Html => Box::new(HtmlRenderer),
+    }
+}
+
+/// Renders into a string, for callers that don't have a writer at hand.
+pub fn render_to_string(renderer: &dyn ReportRenderer, report: &Report) -> Result<String, ShipError> {
+    let mut buffer = Vec::new();
+    renderer.render(report, &mut buffer)?;
+
+    String::from_utf8(buffer).map_err(|err| ShipError::Invalid(err.to_string()))
+}
+
+pub fn render_portfolio_to_string(renderer: &dyn ReportRenderer, portfolio: &Portfolio) -> Result<String, ShipError> {
+    let mut buffer = Vec::new();
+    renderer.render_portfolio(portfolio, &mut buffer)?;
+
+    String::from_utf8(buffer).map_err(|err| ShipError::Invalid(err.to_string()))
+}
+
+impl ReportRenderer for TextRenderer {
+    fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+        let delay = match (&report.longest_legs, &report.longest_delay) {
+            (Some(legs), Some(_)) => format!("The {} longest legs:\n{}", legs.len(), format_longest_legs(legs).join("\n")),
+            _ => format!("The longest delay occured:\n{}", format_longest_delay(report)),
+        };
+
+        write!(out, "\n\t*All times formatted [hh:mm]\n\n\n")?;
+        if report.shows(Section::Total) {
+            writeln!(out, "{}Total transit time: {}", format_state(report), format_total_shipment_time(report))?;
+        }
+        if report.shows(Section::Layovers) {
+            writeln!(out, "Total layover times:\n{}", format_layover_times(report).concat())?;
+        }
+        if report.shows(Section::Stays) {
+            writeln!(out, "Stays:\n{}", format_stays(report).concat())?;
+        }
+        if report.shows(Section::Milestones) {
+            writeln!(out, "Milestones:\n{}", format_milestones(report).concat())?;
+        }
+        if report.shows(Section::Legs) {
+            let order = if report.leg_order == LegOrder::Duration { " (longest first)" } else { "" };
+            writeln!(out, "Legs{}:\n{}", order, format_legs(report).concat())?;
+        }
+        if report.shows(Section::Longest) {
+            writeln!(out, "{}", delay)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl JsonRenderer {
+    /// Drops the keys of every section the report leaves out.
+    fn hide_sections(report: &Report, value: &mut Value) {
+        if let Some(object) = value.as_object_mut() {
+            for section in Section::ALL.iter().filter(|x| !report.shows(**x)) {
+                for key in section.keys() {
+                    object.remove(*key);
+                }
+            }
+        }
+    }
+}
+
+impl ReportRenderer for JsonRenderer {
+    fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+        let mut value = serde_json::to_value(report)?;
+        JsonRenderer::hide_sections(report, &mut value);
+        serde_json::to_writer_pretty(&mut *out, &value)?;
+        writeln!(out)?;
+
+        Ok(())
+    }
+
+    fn render_portfolio(&self, portfolio: &Portfolio, out: &mut dyn Write) -> Result<(), ShipError> {
+        let mut value = serde_json::to_value(portfolio)?;
+        for (index, shipment) in portfolio.shipments.iter().enumerate() {
+            JsonRenderer::hide_sections(&shipment.report, &mut value["shipments"][index]["report"]);
+        }
+        serde_json::to_writer_pretty(&mut *out, &value)?;
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
+
+impl CsvRenderer {
+    /// Every row of one report, without the header.
+    fn rows(report: &Report) -> Vec<[String; 5]> {
+        let mut rows = Vec::new();
+
+        if report.shows(Section::Total) {
+            rows.push(["total".to_string(), String::new(), report.first_scan.to_rfc3339(), report.last_scan.to_rfc3339(), report.total_transit_minutes.to_string()]);
+        }
+        if report.shows(Section::Layovers) {
+            for layover in &report.layovers {
+                rows.push(["layover".to_string(), layover.name.clone(), String::new(), String::new(), layover.minutes.to_string()]);
+            }
+        }
+        if report.shows(Section::Stays) {
+            for stay in &report.stays {
+                rows.push(["stay".to_string(), stay.name.clone(), stay.arrival.datetime.to_rfc3339(), stay.departure.datetime.to_rfc3339(), stay.minutes.to_string()]);
+            }
+        }
+        if report.shows(Section::Milestones) {
+            for milestone in &report.milestones {
+                let minutes = milestone.minutes.map(|x| x.to_string()).unwrap_or_default();
+                rows.push(["milestone".to_string(), milestone.label.clone(), String::new(), String::new(), minutes]);
+            }
+        }
+        if report.shows(Section::Legs) {
+            for leg in &report.legs {
+                let name = format!("{} -> {}", format_location(&leg.from), format_location(&leg.to));
+                rows.push(["leg".to_string(), name, leg.from.datetime.to_rfc3339(), leg.to.datetime.to_rfc3339(), leg.minutes.to_string()]);
+            }
+        }
+        if report.shows(Section::Longest) {
+            for leg in &report.longest() {
+                let name = format!("{} -> {}", format_location(&leg.from), format_location(&leg.to));
+                rows.push(["longest_delay".to_string(), name, leg.from.datetime.to_rfc3339(), leg.to.datetime.to_rfc3339(), leg.minutes.to_string()]);
+            }
+        }
+
+        rows
+    }
+}
+
+impl ReportRenderer for CsvRenderer {
+    fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+        let mut writer = csv::Writer::from_writer(out);
+
+        writer.write_record(["section", "name", "from", "to", "minutes"])?;
+        for row in CsvRenderer::rows(report) {
+            writer.write_record(&row)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// The same rows with the tracking number in front. Failed shipments get a single `failed` row.
+    fn render_portfolio(&self, portfolio: &Portfolio, out: &mut dyn Write) -> Result<(), ShipError> {
+        let mut writer = csv::Writer::from_writer(out);
+
+        writer.write_record(["tracking", "section", "name", "from", "to", "minutes"])?;
+        for shipment in &portfolio.shipments {
+            for row in CsvRenderer::rows(&shipment.report) {
+                writer.write_record(std::iter::once(shipment.key()).chain(row.iter().map(String::as_str)))?;
+            }
+        }
+        for failed in &portfolio.failed {
+            writer.write_record([failed.tracking.as_deref().unwrap_or("untracked"), "failed", &failed.error, "", "", ""])?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl ReportRenderer for MarkdownRenderer {
+    fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+        writeln!(out, "# Shipment report\n")?;
+        if report.shows(Section::Total) {
+            if report.state == ShipmentState::AwaitingFirstMovement {
+                writeln!(out, "**Awaiting first movement:** one scan so far.\n")?;
+            }
+            writeln!(out, "**Total transit time:** {} ({} to {})\n", format_total_shipment_time(report), report.first_scan.to_rfc3339(), report.last_scan.to_rfc3339())?;
+        }
+
+        if report.shows(Section::Layovers) {
+            writeln!(out, "## Layovers\n\n| Location | Visits | Dwell |\n|---|---:|---:|")?;
+            for layover in &report.layovers {
+                writeln!(out, "| {} | {} | {} |", layover.name, layover.visits, format_duration(layover.minutes))?;
+            }
+            writeln!(out)?;
+        }
+
+        if report.shows(Section::Stays) {
+            writeln!(out, "## Stays\n\n| Location | Arrived | Departed | Dwell |\n|---|---|---|---:|")?;
+            for stay in &report.stays {
+                writeln!(out, "| {} | {} | {} | {} |", stay.name, stay.arrival.datetime.to_rfc3339(), stay.departure.datetime.to_rfc3339(), format_duration(stay.minutes))?;
+            }
+            writeln!(out)?;
+        }
+
+        if report.shows(Section::Milestones) {
+            writeln!(out, "## Milestones\n\n| Milestone | Duration |\n|---|---:|")?;
+            for milestone in &report.milestones {
+                let duration = milestone.minutes.map(format_duration).unwrap_or_else(|| "n/a".to_string());
+                writeln!(out, "| {} | {} |", milestone.label, duration)?;
+            }
+            writeln!(out)?;
+        }
+
+        if report.shows(Section::Legs) {
+            writeln!(out, "## Legs\n\n| From | To | Statuses | Duration |\n|---|---|---|---:|")?;
+            for leg in &report.legs {
+                writeln!(out, "| {} | {} | {} -> {} | {} |", format_location(&leg.from), format_location(&leg.to), leg.from.status, leg.to.status, format_duration(leg.minutes))?;
+            }
+            writeln!(out)?;
+        }
+
+        if report.shows(Section::Longest) {
+            let longest = report.longest();
+            match longest.len() {
+                0 => writeln!(out, "## Longest delay\n\nn/a (awaiting first movement)")?,
+                1 => writeln!(out, "## Longest delay\n")?,
+                count => writeln!(out, "## The {} longest legs\n", count)?,
+            }
+            for leg in &longest {
+                writeln!(out, "- **From:** {} - {} ({})", format_location(&leg.from), leg.from.status, leg.from.datetime.to_rfc3339())?;
+                writeln!(out, "- **To:** {} - {} ({})", format_location(&leg.to), leg.to.status, leg.to.datetime.to_rfc3339())?;
+                writeln!(out, "- **Duration:** {}", format_duration(leg.minutes))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::{fixtures::sample_records, models::location::LocationLevel, models::report::ReportOptions, models::shipment::Shipment};
+
+    fn report() -> Report {
+        let records = sample_records();
+        Report::new(&records, LocationLevel::Country).unwrap()
+    }
+
+    #[test]
+    fn text_layout() {
+        let text = render_to_string(&TextRenderer, &report()).unwrap();
+        assert!(text.contains("Total transit time: [138:52]"));
+        assert!(text.contains("Duration: [44:43]"));
+        assert!(text.contains("Legs:\nThe United States Of America -> The United States Of America: [9:27]"));
+    }
+
+    #[test]
+    fn text_top_legs() {
+        let records = sample_records();
+        let options = ReportOptions { top_legs: Some(2), ..ReportOptions::default() };
+        let text = render_to_string(&TextRenderer, &Report::with_options(&records, &options).unwrap()).unwrap();
+        assert!(text.contains("The 2 longest legs:\n1. From:"));
+        assert!(!text.contains("The longest delay occured"));
+    }
+
+    #[test]
+    fn selected_sections() {
+        let records = sample_records();
+        let options = ReportOptions { sections: vec![Section::Total, Section::Layovers], ..ReportOptions::default() };
+        let report = Report::with_options(&records, &options).unwrap();
+
+        let text = render_to_string(&TextRenderer, &report).unwrap();
+        assert!(text.contains("Total transit time: [138:52]\nTotal layover times:"));
+        assert!(!text.contains("Legs:") && !text.contains("longest delay"));
+
+        let json: Value = serde_json::from_str(&render_to_string(&JsonRenderer, &report).unwrap()).unwrap();
+        assert_eq!(json["total_transit_minutes"], 8332);
+        assert!(json.get("legs").is_none() && json.get("stays").is_none());
+
+        let csv = render_to_string(&CsvRenderer, &report).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+    }
+
+    #[test]
+    fn csv_rows() {
+        let csv = render_to_string(&CsvRenderer, &report()).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("section,name,from,to,minutes"));
+        assert_eq!(lines.next(), Some("total,,2017-01-20T09:12:44-05:00,2017-01-26T10:05:33+01:00,8332"));
+        assert!(csv.contains("layover,Germany,,,3080"));
+    }
+
+    #[test]
+    fn markdown_tables() {
+        let markdown = render_to_string(&MarkdownRenderer, &report()).unwrap();
+        assert!(markdown.contains("| Germany | 1 | [51:20] |"));
+        assert!(markdown.contains("| Out for delivery to delivery | n/a |"));
+    }
+
+    #[test]
+    fn portfolio_sections() {
+        let shipments = vec![
+            Shipment::new(Some("A1".to_string()), report().records),
+            Shipment::new(Some("B2".to_string()), report().records[..1].to_vec()),
+        ];
+        let portfolio = Portfolio::new(&shipments, &ReportOptions::default());
+        let text = render_portfolio_to_string(&TextRenderer, &portfolio).unwrap();
+        assert!(text.starts_with("==== A1 ====\n"));
+        assert!(text.contains("Shipments: 2 (2 reported, 0 failed, 1 delivered, 1 awaiting first movement)"));
+        assert!(text.contains("==== B2 ====\n\n\t*All times formatted [hh:mm]\n\n\nAwaiting first movement: one scan at 2017-01-20 09:12"));
+        assert!(text.contains("The longest delay occured:\nn/a (awaiting first movement)"));
+        assert!(text.contains("By lane:\n  The United States Of America -> Germany (1 shipments):\n    Transit:     mean [138:52] median [138:52]"));
+
+        let csv = render_portfolio_to_string(&CsvRenderer, &portfolio).unwrap();
+        assert!(csv.contains("A1,layover,Germany,,,3080"));
+        assert!(csv.contains("B2,total,,2017-01-20T09:12:44-05:00,2017-01-20T09:12:44-05:00,0"));
+        assert!(!csv.contains("B2,longest_delay"));
+    }
+
+    #[test]
+    fn custom_renderer() {
+        struct TotalOnly;
+        impl ReportRenderer for TotalOnly {
+            fn render(&self, report: &Report, out: &mut dyn Write) -> Result<(), ShipError> {
+                write!(out, "{}", report.total_transit_minutes)?;
+                Ok(())
+            }
+        }
+        assert_eq!(render_to_string(&TotalOnly, &report()).unwrap(), "8332");
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+use celes::Country;
+
+use crate::error::ShipError;
+use crate::gazetteer::Gazetteer;
+use crate::models::location::MatchRule;
+
+/// Native-language country names and common short forms that celes doesn't know, by alpha-2 code.
+/// Keys are normalised: lower case with everything but letters and digits removed.
+const COUNTRY_ALIASES: &[(&str, &str)] = &[
+    ("uk", "GB"), ("britain", "GB"), ("unitedstates", "US"), ("america", "US"), ("usa", "US"),
+    ("holland", "NL"), ("nederland", "NL"), ("deutschland", "DE"), ("bundesrepublikdeutschland", "DE"),
+    ("österreich", "AT"), ("osterreich", "AT"), ("schweiz", "CH"), ("suisse", "CH"), ("svizzera", "CH"),
+    ("españa", "ES"), ("espana", "ES"), ("italia", "IT"), ("belgië", "BE"), ("belgie", "BE"), ("belgique", "BE"),
+    ("polska", "PL"), ("česko", "CZ"), ("cesko", "CZ"), ("czechia", "CZ"), ("českárepublika", "CZ"),
+    ("magyarország", "HU"), ("magyarorszag", "HU"), ("sverige", "SE"), ("norge", "NO"), ("danmark", "DK"),
+    ("suomi", "FI"), ("éire", "IE"), ("eire", "IE"), ("brasil", "BR"), ("méxico", "MX"), ("nihon", "JP"),
+    ("nippon", "JP"), ("日本", "JP"), ("中国", "CN"), ("zhongguo", "CN"), ("hellas", "GR"), ("ellada", "GR"),
+    ("türkiye", "TR"), ("turkiye", "TR"), ("россия", "RU"), ("rossiya", "RU"), ("russia", "RU"),
+    ("hrvatska", "HR"), ("slovensko", "SK"), ("slovenija", "SI"), ("lietuva", "LT"), ("latvija", "LV"),
+    ("eesti", "EE"), ("한국", "KR"), ("korea", "KR"), ("bharat", "IN"), ("vietnam", "VN"), ("uae", "AE"),
+];
+
+/// US states and Canadian provinces and territories: code, name and alpha-2 country.
+const SUBDIVISIONS: &[(&str, &str, &str)] = &[
+    ("AL", "Alabama", "US"), ("AK", "Alaska", "US"), ("AZ", "Arizona", "US"), ("AR", "Arkansas", "US"),
+    ("CA", "California", "US"), ("CO", "Colorado", "US"), ("CT", "Connecticut", "US"), ("DE", "Delaware", "US"),
+    ("DC", "District of Columbia", "US"), ("FL", "Florida", "US"), ("GA", "Georgia", "US"), ("HI", "Hawaii", "US"),
+    ("ID", "Idaho", "US"), ("IL", "Illinois", "US"), ("IN", "Indiana", "US"), ("IA", "Iowa", "US"),
+    ("KS", "Kansas", "US"), ("KY", "Kentucky", "US"), ("LA", "Louisiana", "US"), ("ME", "Maine", "US"),
+    ("MD", "Maryland", "US"), ("MA", "Massachusetts", "US"), ("MI", "Michigan", "US"), ("MN", "Minnesota", "US"),
+    ("MS", "Mississippi", "US"), ("MO", "Missouri", "US"), ("MT", "Montana", "US"), ("NE", "Nebraska", "US"),
+    ("NV", "Nevada", "US"), ("NH", "New Hampshire", "US"), ("NJ", "New Jersey", "US"), ("NM", "New Mexico", "US"),
+    ("NY", "New York", "US"), ("NC", "North Carolina", "US"), ("ND", "North Dakota", "US"), ("OH", "Ohio", "US"),
+    ("OK", "Oklahoma", "US"), ("OR", "Oregon", "US"), ("PA", "Pennsylvania", "US"), ("RI", "Rhode Island", "US"),
+    ("SC", "South Carolina", "US"), ("SD", "South Dakota", "US"), ("TN", "Tennessee", "US"), ("TX", "Texas", "US"),
+    ("UT", "Utah", "US"), ("VT", "Vermont", "US"), ("VA", "Virginia", "US"), ("WA", "Washington", "US"),
+    ("WV", "West Virginia", "US"), ("WI", "Wisconsin", "US"), ("WY", "Wyoming", "US"),
+    ("AB", "Alberta", "CA"), ("BC", "British Columbia", "CA"), ("MB", "Manitoba", "CA"), ("NB", "New Brunswick", "CA"),
+    ("NL", "Newfoundland and Labrador", "CA"), ("NS", "Nova Scotia", "CA"), ("NT", "Northwest Territories", "CA"),
+    ("NU", "Nunavut", "CA"), ("ON", "Ontario", "CA"), ("PE", "Prince Edward Island", "CA"), ("QC", "Quebec", "CA"),
+    ("SK", "Saskatchewan", "CA"), ("YT", "Yukon", "CA"),
+];
+
+/// A country resolved from a piece of text, with the rule that matched. Sub-national codes and names
+/// also carry the region code they stand for.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CountryMatch {
+    pub country: Country,
+    pub region: Option<String>,
+    pub rule: MatchRule,
+    /// The text that matched.
+    pub token: String,
+}
+
+/// Resolves country tokens from status lines and location fields. Rules are tried in order: user
+/// aliases, ISO alpha-2 and alpha-3 codes, English country names, the embedded alias dictionary, then
+/// US state and Canadian province codes and names. Codes only match in upper case, so words such as
+/// "to" or "us" in a status aren't read as countries. On its own, a code or name that is both a
+/// country and a state ("GA", "IL", "MA", "Georgia") resolves to the country; `after_place` reads it
+/// as the state when it follows a place.
+#[derive(Debug, Default, Clone)]
+pub struct CountryResolver {
+    aliases: HashMap<String, Country>,
+}
+
+fn normalise(token: &str) -> String {
+    token.chars()
+        .filter(|x| x.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn is_code(token: &str, len: usize) -> bool {
+    token.len() == len && token.chars().all(|x| x.is_ascii_uppercase())
+}
+
+fn country(alpha2: &str) -> Country {
+    Country::from_alpha2(alpha2).unwrap()
+}
+
+fn subdivision(token: &str) -> Option<CountryMatch> {
+    let key = normalise(token);
+    SUBDIVISIONS.iter()
+        .find(|(code, name, _)| (is_code(token, 2) && token == *code) || normalise(name) == key)
+        .map(|(code, _, alpha2)| CountryMatch {
+            country: country(alpha2),
+            region: Some(code.to_string()),
+            rule: MatchRule::Subdivision,
+            token: token.to_string(),
+        })
+}
+
+impl CountryMatch {
+    fn new(country: Country, rule: MatchRule, token: &str) -> CountryMatch {
+        CountryMatch { country, region: None, rule, token: token.to_string() }
+    }
+}
+
+impl CountryResolver {
+    /// Loads user aliases from a JSON object such as `{ "Bayern": "DE", "Blighty": "GB" }`. Values are
+    /// anything celes recognises: a code or an English name.
+    pub fn from_file(path: &Path) -> Result<CountryResolver, ShipError> {
+        let file = File::open(path).map_err(|err| ShipError::from(err).in_file(&path.to_string_lossy()))?;
+        let config: HashMap<String, String> = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| ShipError::Config(format!("Invalid aliases file: {}", err)))?;
+        let mut resolver = CountryResolver::default();
+
+        for (alias, name) in config {
+            let country = Country::from_str(name.trim())
+                .map_err(|_| ShipError::Config(format!("Unknown country for alias '{}': '{}'", alias, name)))?;
+            resolver.add_alias(&alias, country);
+        }
+
+        Ok(resolver)
+    }
+
+    pub fn add_alias(&mut self, alias: &str, country: Country) {
+        self.aliases.insert(normalise(alias), country);
+    }
+
+    pub fn resolve(&self, token: &str) -> Option<CountryMatch> {
+        let token = token.trim();
+        let key = normalise(token);
+        if key.is_empty() {
+            return None;
+        }
+
+        if let Some(country) = self.aliases.get(&key) {
+            return Some(CountryMatch::new(*country, MatchRule::UserAlias, token));
+        }
+        if is_code(token, 2) {
+            if let Ok(country) = Country::from_alpha2(token) {
+                return Some(CountryMatch::new(country, MatchRule::IsoAlpha2, token));
+            }
+        }
+        if is_code(token, 3) {
+            if let Ok(country) = Country::from_alpha3(token) {
+                return Some(CountryMatch::new(country, MatchRule::IsoAlpha3, token));
+            }
+        }
+        if key.len() > 3 && !key.chars().all(|x| x.is_ascii_digit()) {
+            if let Ok(country) = Country::from_str(&key) {
+                return Some(CountryMatch::new(country, MatchRule::CountryName, token));
+            }
+        }
+        if let Some((_, alpha2)) = COUNTRY_ALIASES.iter().find(|(alias, _)| *alias == key) {
+            return Some(CountryMatch::new(country(alpha2), MatchRule::Alias, token));
+        }
+
+        subdivision(token)
+    }
+
+    /// Re-reads a country found right after a place ("Boston, MA", "Atlanta, Georgia") or a preposition
+    /// ("Delivered to ME") as the US state or Canadian province it also names. A code stays a country
+    /// when the place is a known city there ("BERLIN, DE"); a name only becomes a state when the place
+    /// is a known city of the state's country, so "Tbilisi, Georgia" stays a country.
+    pub fn after_place(&self, found: CountryMatch, place: &str, gazetteer: &Gazetteer) -> CountryMatch {
+        let known_in = |country: &Country| gazetteer.coordinates(place, country).is_some();
+        let state = match found.rule {
+            MatchRule::IsoAlpha2 | MatchRule::CountryName => subdivision(&found.token),
+            _ => None,
+        };
+
+        match state {
+            Some(state) if found.rule == MatchRule::IsoAlpha2 && !known_in(&found.country) => state,
+            Some(state) if found.rule == MatchRule::CountryName && known_in(&state.country) => state,
+            _ => found,
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn resolve_by_rule() {
+        let resolver = CountryResolver::default();
+        let resolve = |x: &str| resolver.resolve(x).map(|x| (x.country.alpha2, x.rule));
+
+        assert_eq!(resolve("DE"), Some(("DE", MatchRule::IsoAlpha2)));
+        assert_eq!(resolve("USA"), Some(("US", MatchRule::IsoAlpha3)));
+        assert_eq!(resolve("GERMANY"), Some(("DE", MatchRule::CountryName)));
+        assert_eq!(resolve("United Kingdom"), Some(("GB", MatchRule::CountryName)));
+        assert_eq!(resolve("UK"), Some(("GB", MatchRule::Alias)));
+        assert_eq!(resolve("Deutschland"), Some(("DE", MatchRule::Alias)));
+        assert_eq!(resolve("Österreich"), Some(("AT", MatchRule::Alias)));
+        assert_eq!(resolve("FL"), Some(("US", MatchRule::Subdivision)));
+        assert_eq!(resolve("Ontario"), Some(("CA", MatchRule::Subdivision)));
+        assert_eq!(resolver.resolve("ON").unwrap().region, Some("ON".to_string()));
+        assert_eq!(resolve("to"), None);
+        assert_eq!(resolve("840"), None);
+    }
+
+    #[test]
+    fn user_aliases_first() {
+        let resolver = CountryResolver::from_file(Path::new("tests/data/config/aliases.json")).unwrap();
+        let found = resolver.resolve("Bayern").unwrap();
+
+        assert_eq!(found.country, Country::germany());
+        assert_eq!(found.rule, MatchRule::UserAlias);
+        assert_eq!(resolver.resolve("CA").unwrap().rule, MatchRule::IsoAlpha2);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::calc::{calc_business_time, diff_datetime, location_name};
+use crate::calendar::BusinessCalendar;
+use crate::models::{location::LocationLevel, record::Record, stay::Stay};
+
+/// Splits the timeline into consecutive stays at the given level. A stay ends as soon as a record
+/// names a different location, so US -> CA -> US gives two separate US visits. Records without a
+/// name at this level neither start nor break a stay.
+pub fn segment_stays(records: &[Record], level: LocationLevel) -> Vec<Stay> {
+    segment_stays_with(records, level, None)
+}
+
+/// The same stays, each with its business time from arrival to departure when a calendar is given.
+pub fn segment_stays_with(records: &[Record], level: LocationLevel, calendar: Option<&BusinessCalendar>) -> Vec<Stay> {
+    let mut stays: Vec<Stay> = Vec::new();
+
+    for rec in records {
+        let name = match location_name(rec, level) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        match stays.last_mut() {
+            Some(stay) if stay.name == name => {
+                stay.minutes = diff_datetime(&stay.arrival.datetime, &rec.datetime);
+                stay.departure = rec.clone();
+            },
+            _ => stays.push(Stay::new(name, rec.clone(), rec.clone(), 0)),
+        }
+    }
+
+    if let Some(calendar) = calendar {
+        for stay in &mut stays {
+            stay.business_minutes = Some(calc_business_time(calendar, &stay.arrival, &stay.departure));
+        }
+    }
+
+    stays
+}
+
+
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use celes::Country;
+
+    use crate::fixtures::record_at;
+    use crate::models::location::Location;
+
+    fn record(datetime: &str, country: Option<Country>) -> Record {
+        record_at(datetime, "Processed", country.map(Location::from_country))
+    }
+
+    #[test]
+    fn split_revisits() {
+        let recs = vec![
+            record("2017-01-20 10:00:00 +0000", Some(Country::the_united_states_of_america())),
+            record("2017-01-20 12:00:00 +0000", Some(Country::the_united_states_of_america())),
+            record("2017-01-21 09:00:00 +0000", Some(Country::canada())),
+            record("2017-01-22 09:30:00 +0000", Some(Country::canada())),
+            record("2017-01-23 08:00:00 +0000", Some(Country::the_united_states_of_america())),
+            record("2017-01-23 09:15:00 +0000", Some(Country::the_united_states_of_america())),
+        ];
+        let stays = segment_stays(&recs, LocationLevel::Country);
+        assert_eq!(stays.len(), 3);
+        assert_eq!(stays[0].minutes, 120);
+        assert_eq!(stays[1].name, "Canada");
+        assert_eq!(stays[1].minutes, 1470);
+        assert_eq!(stays[2].arrival, recs[4]);
+        assert_eq!(stays[2].departure, recs[5]);
+        assert_eq!(stays[2].minutes, 75);
+    }
+
+    #[test]
+    fn skip_unnamed_records() {
+        let recs = vec![
+            record("2017-01-20 10:00:00 +0000", Some(Country::germany())),
+            record("2017-01-20 11:00:00 +0000", None),
+            record("2017-01-20 12:00:00 +0000", Some(Country::germany())),
+        ];
+        let stays = segment_stays(&recs, LocationLevel::Country);
+        assert_eq!(stays.len(), 1);
+        assert_eq!(stays[0].minutes, 120);
+    }
+}
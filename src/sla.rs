@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+use celes::Country;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::calc::{find_event, location_name};
+use crate::calendar::BusinessCalendar;
+use crate::error::ShipError;
+use crate::models::event_kind::EventKind;
+use crate::models::portfolio::ShipmentReport;
+use crate::models::location::UNKNOWN_LOCATION;
+use crate::models::report::Report;
+use crate::models::sla::{BreachCause, SlaEvaluation, SlaResult, SlaStatus};
+use crate::stats::shipment_lane;
+
+#[derive(Debug, Deserialize)]
+struct CommitmentConfig {
+    name: Option<String>,
+    origin: Option<String>,
+    destination: Option<String>,
+    tracking: Option<String>,
+    #[serde(default)]
+    days: i64,
+    #[serde(default)]
+    hours: i64,
+    #[serde(default)]
+    business: bool,
+}
+
+/// A promised transit time for a lane. A missing origin, destination or tracking pattern matches any shipment.
+/// A `business` commitment counts working days and business time rather than elapsed time.
+#[derive(Debug, Clone)]
+pub struct Commitment {
+    pub name: String,
+    pub origin: Option<Country>,
+    pub destination: Option<Country>,
+    pub tracking: Option<Regex>,
+    pub days: i64,
+    pub hours: i64,
+    pub business: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SlaConfig {
+    pub commitments: Vec<Commitment>,
+}
+
+fn parse_country(name: &str) -> Result<Country, ShipError> {
+    Country::from_str(name.trim()).map_err(|_| ShipError::Config(format!("Unknown SLA country: '{}'", name)))
+}
+
+impl Commitment {
+    fn from_config(config: CommitmentConfig) -> Result<Commitment, ShipError> {
+        let origin = config.origin.as_deref().map(parse_country).transpose()?;
+        let destination = config.destination.as_deref().map(parse_country).transpose()?;
+        let tracking = config.tracking.as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|err| ShipError::Config(format!("Invalid SLA tracking pattern: {}", err)))?;
+        if config.days * 24 + config.hours <= 0 {
+            return Err(ShipError::Config("Each SLA commitment needs a positive 'days' and/or 'hours' limit.".to_string()));
+        }
+
+        let code = |country: &Option<Country>| country.as_ref().map(|x| x.alpha2).unwrap_or("*");
+        let name = config.name.unwrap_or_else(|| format!("{} -> {}", code(&origin), code(&destination)));
+
+        Ok(Commitment { name, origin, destination, tracking, days: config.days, hours: config.hours, business: config.business })
+    }
+
+    /// The limit in minutes. A business day is as long as the calendar's working hours, so business
+    /// commitments need a calendar.
+    pub fn limit_minutes(&self, calendar: Option<&BusinessCalendar>) -> Result<i64, ShipError> {
+        match (self.business, calendar) {
+            (false, _) => Ok(self.days * 24 * 60 + self.hours * 60),
+            (true, Some(calendar)) => Ok(self.days * calendar.day_minutes() + self.hours * 60),
+            (true, None) => Err(ShipError::Config(format!("SLA commitment '{}' counts business days and needs --business-time or --calendar.", self.name))),
+        }
+    }
+
+    pub fn matches(&self, tracking: Option<&str>, report: &Report) -> bool {
+        let country = |index: usize| report.records.get(index)
+            .and_then(|x| x.location.as_ref())
+            .and_then(|x| x.country.as_ref());
+        let last = report.records.len().saturating_sub(1);
+
+        self.origin.as_ref().is_none_or(|x| country(0) == Some(x))
+            && self.destination.as_ref().is_none_or(|x| country(last) == Some(x))
+            && self.tracking.as_ref().is_none_or(|x| tracking.is_some_and(|t| x.is_match(t)))
+    }
+}
+
+impl SlaConfig {
+    /// Loads commitments from a JSON array of `{ "name": "US -> DE express", "origin": "US",
+    /// "destination": "DE", "days": 3 }` objects. The first commitment that matches a shipment applies.
+    pub fn from_file(path: &Path) -> Result<SlaConfig, ShipError> {
+        let file = File::open(path).map_err(|err| ShipError::from(err).in_file(&path.to_string_lossy()))?;
+        let configs: Vec<CommitmentConfig> = serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| ShipError::Config(format!("Invalid SLA file: {}", err)))?;
+        let commitments = configs.into_iter()
+            .map(Commitment::from_config)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SlaConfig { commitments })
+    }
+
+    pub fn find(&self, tracking: Option<&str>, report: &Report) -> Option<&Commitment> {
+        self.commitments.iter().find(|x| x.matches(tracking, report))
+    }
+}
+
+/// The longest stay or the longest leg, whichever took more time.
+fn breach_cause(report: &Report) -> Option<BreachCause> {
+    let name = |record| location_name(record, report.group_by).unwrap_or_else(|| UNKNOWN_LOCATION.to_string());
+    let stay = report.stays.iter().max_by_key(|x| x.minutes);
+
+    match (stay, &report.longest_delay) {
+        (Some(stay), Some(leg)) if stay.minutes < leg.minutes => Some(BreachCause::Leg { from: name(&leg.from), to: name(&leg.to), minutes: leg.minutes }),
+        (None, Some(leg)) => Some(BreachCause::Leg { from: name(&leg.from), to: name(&leg.to), minutes: leg.minutes }),
+        (Some(stay), _) => Some(BreachCause::Locale { name: stay.name.clone(), minutes: stay.minutes }),
+        (None, None) => None,
+    }
+}
+
+/// Measures a shipment against a commitment. Business commitments are measured against the report's
+/// business time, so the report must have been built with the same calendar.
+pub fn evaluate_sla(commitment: &Commitment, tracking: Option<&str>, report: &Report, calendar: Option<&BusinessCalendar>) -> Result<SlaResult, ShipError> {
+    let limit = commitment.limit_minutes(calendar)?;
+    let elapsed = match commitment.business {
+        true => report.total_business_minutes
+            .ok_or_else(|| ShipError::Config(format!("SLA commitment '{}' needs a report with business time.", commitment.name)))?,
+        false => report.total_transit_minutes,
+    };
+    let margin = limit - elapsed;
+    let delivered = find_event(&report.records, EventKind::Delivered).is_some();
+    let status = match (margin < 0, delivered) {
+        (true, _) => SlaStatus::Breached,
+        (false, true) => SlaStatus::Met,
+        (false, false) => SlaStatus::Pending,
+    };
+
+    Ok(SlaResult {
+        tracking: tracking.map(str::to_string),
+        commitment: commitment.name.clone(),
+        lane: shipment_lane(report),
+        limit_minutes: limit,
+        elapsed_minutes: elapsed,
+        margin_minutes: margin,
+        status,
+        cause: if status == SlaStatus::Breached { breach_cause(report) } else { None },
+    })
+}
+
+pub fn evaluate_shipments(config: &SlaConfig, shipments: &[ShipmentReport], calendar: Option<&BusinessCalendar>) -> Result<SlaEvaluation, ShipError> {
+    let mut results = Vec::new();
+    let mut unmatched = Vec::new();
+
+    for shipment in shipments {
+        match config.find(shipment.tracking.as_deref(), &shipment.report) {
+            Some(commitment) => results.push(evaluate_sla(commitment, shipment.tracking.as_deref(), &shipment.report, calendar)?),
+            None => unmatched.push(shipment.key().to_string()),
+        }
+    }
+
+    let count = |status: SlaStatus| results.iter().filter(|x: &&SlaResult| x.status == status).count();
+    Ok(SlaEvaluation {
+        met: count(SlaStatus::Met),
+        pending: count(SlaStatus::Pending),
+        breached: count(SlaStatus::Breached),
+        results,
+        unmatched,
+    })
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use chrono::NaiveTime;
+
+    use crate::{fixtures::sample_records, models::location::LocationLevel, models::report::ReportOptions};
+
+    fn report() -> Report {
+        let records = sample_records();
+        Report::new(&records, LocationLevel::Country).unwrap()
+    }
+
+    #[test]
+    fn sla_from_file() {
+        let config = SlaConfig::from_file(Path::new("tests/data/config/sla.json")).unwrap();
+        let commitment = config.find(None, &report()).unwrap();
+        assert_eq!(commitment.name, "US -> DE express");
+        assert_eq!(commitment.limit_minutes(None).unwrap(), 3 * 24 * 60);
+    }
+
+    #[test]
+    fn breach_by_longest_stay() {
+        let config = SlaConfig::from_file(Path::new("tests/data/config/sla.json")).unwrap();
+        let result = evaluate_sla(&config.commitments[0], None, &report(), None).unwrap();
+        assert_eq!(result.status, SlaStatus::Breached);
+        assert_eq!(result.margin_minutes, 4320 - 8332);
+        assert_eq!(result.cause, Some(BreachCause::Locale { name: "The United States Of America".to_string(), minutes: 4729 }));
+
+        let generous = Commitment { days: 7, ..config.commitments[0].clone() };
+        assert_eq!(evaluate_sla(&generous, None, &report(), None).unwrap().status, SlaStatus::Met);
+    }
+
+    #[test]
+    fn business_days() {
+        let config = SlaConfig::from_file(Path::new("tests/data/config/sla.json")).unwrap();
+        let commitment = Commitment { business: true, ..config.commitments[0].clone() };
+        assert!(matches!(evaluate_sla(&commitment, None, &report(), None), Err(ShipError::Config(_))));
+
+        let calendar = BusinessCalendar::default();
+        let options = ReportOptions { calendar: Some(calendar.clone()), ..ReportOptions::default() };
+        let report = Report::with_options(&sample_records(), &options).unwrap();
+        let result = evaluate_sla(&commitment, None, &report, Some(&calendar)).unwrap();
+        assert_eq!(result.elapsed_minutes, report.total_business_minutes.unwrap());
+        assert_eq!(result.limit_minutes, 3 * 24 * 60);
+        assert_eq!(result.status, SlaStatus::Breached);
+
+        let office = BusinessCalendar { hours: Some((NaiveTime::from_hms_opt(9, 0, 0).unwrap(), NaiveTime::from_hms_opt(17, 0, 0).unwrap())), ..calendar };
+        assert_eq!(commitment.limit_minutes(Some(&office)).unwrap(), 3 * 8 * 60);
+    }
+}
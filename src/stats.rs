@@ -0,0 +1,159 @@
+use itertools::Itertools;
+
+use crate::calc::{calc_layover_times_by, location_name};
+use crate::models::location::{LocationLevel, UNKNOWN_LOCATION};
+use crate::models::report::{Report, ShipmentState};
+use crate::models::stats::{CountryLayover, Distribution, GroupStats, PortfolioStats, ShipmentStats};
+
+/// Nearest-rank percentile of sorted values: the smallest value with at least `percent`% of values at or below it.
+fn percentile(sorted: &[i64], percent: usize) -> i64 {
+    let rank = (percent * sorted.len()).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+pub fn calc_distribution(values: &[i64]) -> Option<Distribution> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let sorted: Vec<i64> = values.iter().copied().sorted().collect();
+    let count = sorted.len();
+    let median = match count % 2 {
+        0 => (sorted[count / 2 - 1] + sorted[count / 2]) / 2,
+        _ => sorted[count / 2],
+    };
+
+    Some(Distribution {
+        count,
+        mean: sorted.iter().sum::<i64>() / count as i64,
+        median,
+        p90: percentile(&sorted, 90),
+        p95: percentile(&sorted, 95),
+    })
+}
+
+/// `origin -> destination` by country of the first and last scan.
+pub fn shipment_lane(report: &Report) -> String {
+    let name = |index: Option<usize>| index
+        .and_then(|x| report.records.get(x))
+        .and_then(|x| location_name(x, LocationLevel::Country))
+        .unwrap_or_else(|| UNKNOWN_LOCATION.to_string());
+
+    format!("{} -> {}", name(Some(0)), name(report.records.len().checked_sub(1)))
+}
+
+/// ISO week of the first scan, in its local time, e.g. `2017-W03`.
+pub fn ship_week(report: &Report) -> String {
+    report.first_scan.format("%G-W%V").to_string()
+}
+
+/// Shipments still awaiting their first movement count towards `shipments` and layovers, but not towards
+/// transit or longest leg, where their zero would drag the figures down.
+pub fn calc_shipment_stats(reports: &[&Report]) -> ShipmentStats {
+    let transit: Vec<i64> = reports.iter()
+        .filter(|x| x.state != ShipmentState::AwaitingFirstMovement)
+        .map(|x| x.total_transit_minutes)
+        .collect();
+    let longest_leg: Vec<i64> = reports.iter().filter_map(|x| x.longest_delay.as_ref()).map(|x| x.minutes).collect();
+    let dwells: Vec<_> = reports.iter()
+        .flat_map(|x| calc_layover_times_by(&x.records, LocationLevel::Country).unwrap_or_default())
+        .collect();
+    let layovers = dwells.iter()
+        .map(|x| x.name.clone())
+        .unique()
+        .filter_map(|country| {
+            let minutes: Vec<i64> = dwells.iter().filter(|x| x.name == country).map(|x| x.minutes).collect();
+            calc_distribution(&minutes).map(|minutes| CountryLayover { country, minutes })
+        })
+        .collect();
+
+    ShipmentStats {
+        shipments: reports.len(),
+        transit: calc_distribution(&transit),
+        longest_leg: calc_distribution(&longest_leg),
+        layovers,
+    }
+}
+
+/// Groups reports by key in order of first appearance and computes statistics for each group.
+fn calc_group_stats<F>(reports: &[&Report], key: F) -> Vec<GroupStats>
+where
+    F: Fn(&Report) -> String,
+{
+    reports.iter()
+        .map(|x| key(x))
+        .unique()
+        .map(|group| {
+            let members: Vec<&Report> = reports.iter().copied().filter(|x| key(x) == group).collect();
+            GroupStats { key: group, stats: calc_shipment_stats(&members) }
+        })
+        .collect()
+}
+
+/// Statistics over every report, by lane and by ship week. Weeks are listed in calendar order.
+pub fn calc_portfolio_stats(reports: &[&Report]) -> PortfolioStats {
+    let mut weeks = calc_group_stats(reports, ship_week);
+    weeks.sort_by(|a, b| a.key.cmp(&b.key));
+
+    PortfolioStats {
+        overall: calc_shipment_stats(reports),
+        lanes: calc_group_stats(reports, shipment_lane),
+        weeks,
+    }
+}
+
+
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use celes::Country;
+
+    use crate::fixtures::record_at;
+    use crate::models::location::Location;
+
+    #[test]
+    fn distribution_percentiles() {
+        let values: Vec<i64> = (1..=20).map(|x| x * 10).collect();
+        let dist = calc_distribution(&values).unwrap();
+        assert_eq!(dist.count, 20);
+        assert_eq!(dist.mean, 105);
+        assert_eq!(dist.median, 105);
+        assert_eq!(dist.p90, 180);
+        assert_eq!(dist.p95, 190);
+
+        let single = calc_distribution(&[42]).unwrap();
+        assert_eq!((single.median, single.p90, single.p95), (42, 42, 42));
+        assert!(calc_distribution(&[]).is_none());
+    }
+
+    #[test]
+    fn lane_by_first_and_last_country() {
+        let recs = vec![
+            record_at("2017-01-20 10:00:00 +0000", "Shipment picked up", Some(Location::from_country(Country::the_united_states_of_america()))),
+            record_at("2017-01-21 09:00:00 +0000", "Processed", Some(Location::from_country(Country::canada()))),
+            record_at("2017-01-23 08:00:00 +0000", "Delivered", Some(Location::from_country(Country::germany()))),
+        ];
+        let report = Report::new(&recs, LocationLevel::Country).unwrap();
+        assert_eq!(shipment_lane(&report), format!("{} -> {}", Country::the_united_states_of_america().long_name, Country::germany().long_name));
+
+        let unlocated = vec![
+            record_at("2017-01-20 10:00:00 +0000", "Shipment picked up", None),
+            record_at("2017-01-23 08:00:00 +0000", "Delivered", Some(Location::from_country(Country::germany()))),
+        ];
+        let report = Report::new(&unlocated, LocationLevel::Country).unwrap();
+        assert_eq!(shipment_lane(&report), format!("{} -> {}", UNKNOWN_LOCATION, Country::germany().long_name));
+    }
+
+    #[test]
+    fn week_across_year_boundary() {
+        // Sunday night at UTC-5 is already Monday, 2017-W01, in UTC.
+        let recs = vec![record_at("2017-01-01 23:30:00 -0500", "Shipment picked up", None)];
+        assert_eq!(ship_week(&Report::new(&recs, LocationLevel::Country).unwrap()), "2016-W52");
+
+        let recs = vec![record_at("2017-01-23 16:02:24 +0000", "Shipment picked up", None)];
+        assert_eq!(ship_week(&Report::new(&recs, LocationLevel::Country).unwrap()), "2017-W04");
+    }
+}
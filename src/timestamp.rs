@@ -0,0 +1,306 @@
+use std::ops::Range;
+use std::sync::OnceLock;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc};
+use regex::{Captures, Regex};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TimestampFormat {
+    Iso,
+    MonthDayYear,
+    DayMonthYear,
+    DottedDayMonthYear,
+    DayMonthName,
+    UnixEpoch,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Timestamp {
+    pub local: NaiveDateTime,
+    pub offset: Option<FixedOffset>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Spec {
+    Known(TimestampFormat),
+    Custom(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct TimestampParser {
+    spec: Spec,
+    pattern: Option<Regex>,
+}
+
+const TIME_PATTERN: &str = r"[T ,]*(?P<time>\d{1,2}:\d{2}(?::\d{2})?)(?:\s*(?P<ampm>[AaPp][Mm])\b)?";
+
+impl TimestampFormat {
+    pub const ALL: [TimestampFormat; 6] = [
+        TimestampFormat::Iso,
+        TimestampFormat::MonthDayYear,
+        TimestampFormat::DayMonthYear,
+        TimestampFormat::DottedDayMonthYear,
+        TimestampFormat::DayMonthName,
+        TimestampFormat::UnixEpoch,
+    ];
+
+    pub fn from_name(name: &str) -> Option<TimestampFormat> {
+        match name {
+            "iso" => Some(TimestampFormat::Iso),
+            "mdy" => Some(TimestampFormat::MonthDayYear),
+            "dmy" => Some(TimestampFormat::DayMonthYear),
+            "dotted" => Some(TimestampFormat::DottedDayMonthYear),
+            "day-month" => Some(TimestampFormat::DayMonthName),
+            "epoch" => Some(TimestampFormat::UnixEpoch),
+            _ => None,
+        }
+    }
+
+    fn pattern(&self) -> String {
+        match self {
+            TimestampFormat::Iso => format!(r"(?P<date>\d{{4}}-\d{{2}}-\d{{2}}){}(?:\.\d+)?(?P<offset>Z|[+-]\d{{2}}:?\d{{2}})?", TIME_PATTERN),
+            TimestampFormat::MonthDayYear | TimestampFormat::DayMonthYear => format!(r"(?P<date>\d{{1,2}}/\d{{1,2}}/\d{{4}}){}", TIME_PATTERN),
+            TimestampFormat::DottedDayMonthYear => format!(r"(?P<date>\d{{1,2}}\.\d{{1,2}}\.\d{{4}}){}", TIME_PATTERN),
+            TimestampFormat::DayMonthName => format!(r"(?P<date>\d{{1,2}} [A-Za-z]{{3}} \d{{4}}){}", TIME_PATTERN),
+            TimestampFormat::UnixEpoch => r"\b(?P<epoch>\d{10})\b".to_string(),
+        }
+    }
+
+    /// The pattern detection uses. A bare ten-digit number may just as well be a tracking or phone
+    /// number, so epoch seconds are only detected at the start of a line; anywhere else they need
+    /// `--time-format epoch`.
+    fn detect_pattern(&self) -> String {
+        match self {
+            TimestampFormat::UnixEpoch => format!(r"^\s*{}", self.pattern()),
+            _ => self.pattern(),
+        }
+    }
+
+    fn date_format(&self) -> &'static str {
+        match self {
+            TimestampFormat::Iso => "%Y-%m-%d",
+            TimestampFormat::MonthDayYear => "%m/%d/%Y",
+            TimestampFormat::DayMonthYear => "%d/%m/%Y",
+            TimestampFormat::DottedDayMonthYear => "%d.%m.%Y",
+            TimestampFormat::DayMonthName => "%d %b %Y",
+            TimestampFormat::UnixEpoch => "",
+        }
+    }
+
+    fn parse_captures(&self, caps: &Captures) -> Option<Timestamp> {
+        if let Some(epoch) = caps.name("epoch") {
+            let local = DateTime::from_timestamp(epoch.as_str().parse().ok()?, 0)?.naive_utc();
+            return Some(Timestamp { local, offset: Some(Utc.fix()) });
+        }
+
+        let date = NaiveDate::parse_from_str(caps.name("date")?.as_str(), self.date_format()).ok()?;
+        let time = parse_time(caps.name("time")?.as_str(), caps.name("ampm").map(|x| x.as_str()))?;
+        let offset = match caps.name("offset") {
+            Some(offset) => Some(parse_offset(offset.as_str())?),
+            None => None,
+        };
+
+        Some(Timestamp { local: date.and_time(time), offset })
+    }
+}
+
+fn known_parsers() -> &'static [TimestampParser] {
+    static PARSERS: OnceLock<Vec<TimestampParser>> = OnceLock::new();
+    PARSERS.get_or_init(|| TimestampFormat::ALL.iter()
+        .map(|format| TimestampParser { spec: Spec::Known(*format), pattern: Some(Regex::new(&format.detect_pattern()).unwrap()) })
+        .collect())
+}
+
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    if offset == "Z" {
+        return Some(Utc.fix());
+    }
+
+    let digits = offset[1..].replace(':', "");
+    let minutes = digits[..2].parse::<i32>().ok()? * 60 + digits[2..].parse::<i32>().ok()?;
+    let sign = if offset.starts_with('-') { -1 } else { 1 };
+
+    FixedOffset::east_opt(sign * minutes * 60)
+}
+
+fn parse_custom(value: &str, fmt: &str) -> Option<Timestamp> {
+    if ["%z", "%:z", "%#z"].iter().any(|x| fmt.contains(x)) {
+        let datetime = DateTime::parse_from_str(value, fmt).ok()?;
+        return Some(Timestamp { local: datetime.naive_local(), offset: Some(*datetime.offset()) });
+    }
+
+    NaiveDateTime::parse_from_str(value, fmt).ok()
+        .map(|local| Timestamp { local, offset: None })
+}
+
+fn parse_time(time: &str, ampm: Option<&str>) -> Option<NaiveTime> {
+    match ampm {
+        Some(ampm) => {
+            let time = format!("{} {}", time, ampm.to_uppercase());
+            NaiveTime::parse_from_str(&time, "%I:%M:%S %p")
+                .or_else(|_| NaiveTime::parse_from_str(&time, "%I:%M %p"))
+                .ok()
+        },
+        None => NaiveTime::parse_from_str(time, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+            .ok(),
+    }
+}
+
+impl TimestampParser {
+    pub fn new(spec: &str) -> TimestampParser {
+        match TimestampFormat::from_name(spec) {
+            Some(format) => Self::known(format),
+            None => TimestampParser { spec: Spec::Custom(spec.to_string()), pattern: None },
+        }
+    }
+
+    pub fn known(format: TimestampFormat) -> TimestampParser {
+        let pattern = Regex::new(&format.pattern()).unwrap();
+        TimestampParser { spec: Spec::Known(format), pattern: Some(pattern) }
+    }
+
+    pub fn format(&self) -> Option<TimestampFormat> {
+        match self.spec {
+            Spec::Known(format) => Some(format),
+            Spec::Custom(..) => None,
+        }
+    }
+
+    /// Picks the known format that parses the most lines, preferring earlier formats on a tie.
+    pub fn detect(lines: &[String]) -> Option<TimestampParser> {
+        let parsers = known_parsers();
+        let scores: Vec<usize> = parsers.iter()
+            .map(|parser| lines.iter().filter(|line| parser.find(line).is_some()).count())
+            .collect();
+        let best = *scores.iter().max()?;
+
+        if best == 0 {
+            return None;
+        }
+
+        parsers.iter()
+            .zip(scores)
+            .find(|(_, score)| *score == best)
+            .map(|(parser, _)| parser.clone())
+    }
+
+    /// Locates a timestamp inside a line, returning it with the byte range it covered.
+    /// Custom formats are only matched at the start of the line.
+    pub fn find(&self, line: &str) -> Option<(Timestamp, Range<usize>)> {
+        match (&self.spec, &self.pattern) {
+            (Spec::Known(format), Some(pattern)) => pattern.captures_iter(line)
+                .find_map(|caps| {
+                    let datetime = format.parse_captures(&caps)?;
+                    Some((datetime, caps.get(0)?.range()))
+                }),
+            (Spec::Custom(fmt), _) => {
+                let start = line.len() - line.trim_start().len();
+                let mut ends: Vec<usize> = line[start..].char_indices()
+                    .map(|(index, _)| start + index)
+                    .skip(1)
+                    .chain(std::iter::once(line.len()))
+                    .collect();
+                ends.reverse();
+
+                ends.into_iter()
+                    .find_map(|end| parse_custom(&line[start..end], fmt)
+                              .map(|timestamp| (timestamp, start..end)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Parses a value holding nothing but a timestamp, such as a JSON field or a mapped CSV cell.
+    pub fn parse(&self, value: &str) -> Option<Timestamp> {
+        let value = value.trim();
+        self.find(value)
+            .filter(|(_, range)| range.start == 0 && range.end == value.len())
+            .map(|(timestamp, _)| timestamp)
+    }
+
+    pub fn parse_any(value: &str) -> Option<Timestamp> {
+        known_parsers().iter()
+            .find_map(|parser| parser.parse(value))
+    }
+}
+
+impl PartialEq for TimestampParser {
+    fn eq(&self, other: &Self) -> bool {
+        self.spec == other.spec
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn parse_known_formats() {
+        let expected = datetime("2017-01-23 16:02:00");
+        let local = |value: &str| TimestampParser::parse_any(value).map(|x| x.local);
+        assert_eq!(local("2017-01-23T16:02:00+01:00"), Some(expected));
+        assert_eq!(local("01/23/2017 16:02"), Some(expected));
+        assert_eq!(local("23.01.2017 16:02"), Some(expected));
+        assert_eq!(local("23 Jan 2017 4:02 PM"), Some(expected));
+        assert_eq!(local("1485187320"), Some(expected));
+    }
+
+    #[test]
+    fn parse_offsets() {
+        assert_eq!(TimestampParser::parse_any("2017-01-23T16:02:00+01:00").unwrap().offset, FixedOffset::east_opt(3600));
+        assert_eq!(TimestampParser::parse_any("2017-01-23T16:02:00-0530").unwrap().offset, FixedOffset::west_opt(19800));
+        assert_eq!(TimestampParser::parse_any("2017-01-23T16:02:00Z").unwrap().offset, FixedOffset::east_opt(0));
+        assert_eq!(TimestampParser::parse_any("1485187320").unwrap().offset, FixedOffset::east_opt(0));
+        assert_eq!(TimestampParser::parse_any("2017-01-23 16:02").unwrap().offset, None);
+    }
+
+    #[test]
+    fn find_in_line() {
+        let parser = TimestampParser::known(TimestampFormat::Iso);
+        let line = "2017-01-23, 16:02:24, Departed Facility in CINCINNATI HUB,OH-USA";
+        let (found, range) = parser.find(line).unwrap();
+        assert_eq!(found.local, datetime("2017-01-23 16:02:24"));
+        assert_eq!(&line[range], "2017-01-23, 16:02:24");
+    }
+
+    #[test]
+    fn detect_day_first() {
+        let lines = vec![
+            "05/01/2017 09:12, Shipment picked up".to_string(),
+            "23/01/2017 16:02, Departed Facility".to_string(),
+        ];
+        let parser = TimestampParser::detect(&lines).unwrap();
+        assert_eq!(parser.format(), Some(TimestampFormat::DayMonthYear));
+    }
+
+    #[test]
+    fn epoch_only_at_line_start() {
+        let numbered = vec![
+            "Shipment picked up, ref 4155550123".to_string(),
+            "Delivered, tracking 1234567890".to_string(),
+        ];
+        assert!(TimestampParser::detect(&numbered).is_none());
+
+        let dated = vec!["2017-01-23 16:02:24, Departed, ref 1485187320".to_string()];
+        assert_eq!(TimestampParser::detect(&dated).unwrap().format(), Some(TimestampFormat::Iso));
+
+        let epoch = vec!["1485187320, Departed Facility".to_string()];
+        assert_eq!(TimestampParser::detect(&epoch).unwrap().format(), Some(TimestampFormat::UnixEpoch));
+        assert!(TimestampParser::new("epoch").find("Departed 1485187320").is_some());
+    }
+
+    #[test]
+    fn custom_format() {
+        let parser = TimestampParser::new("%Y%m%d %H%M");
+        let (found, range) = parser.find("20170123 1602 Departed Facility").unwrap();
+        assert_eq!(found.local, datetime("2017-01-23 16:02:00"));
+        assert_eq!(range, 0..13);
+    }
+}
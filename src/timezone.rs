@@ -0,0 +1,251 @@
+use std::str::FromStr;
+use celes::Country;
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc};
+use regex::Regex;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DstRule {
+    None,
+    /// Last Sunday of March to the last Sunday of October, switching at 01:00 UTC.
+    Eu,
+    /// Second Sunday of March to the first Sunday of November, switching at 02:00 local.
+    Us,
+    /// First Sunday of October to the first Sunday of April, switching at 02:00 standard time.
+    AuSouth,
+    /// Last Sunday of September to the first Sunday of April, switching at 02:00 standard time.
+    Nz,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TimeZoneRule {
+    pub alpha2: &'static str,
+    pub standard_minutes: i32,
+    pub dst: DstRule,
+}
+
+/// One representative zone per country: countries spanning several zones use their most populous one.
+const RULES: &[TimeZoneRule] = &[
+    TimeZoneRule { alpha2: "US", standard_minutes: -300, dst: DstRule::Us },
+    TimeZoneRule { alpha2: "CA", standard_minutes: -300, dst: DstRule::Us },
+    TimeZoneRule { alpha2: "MX", standard_minutes: -360, dst: DstRule::None },
+    TimeZoneRule { alpha2: "BR", standard_minutes: -180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "AR", standard_minutes: -180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "CL", standard_minutes: -240, dst: DstRule::None },
+    TimeZoneRule { alpha2: "CO", standard_minutes: -300, dst: DstRule::None },
+    TimeZoneRule { alpha2: "PE", standard_minutes: -300, dst: DstRule::None },
+    TimeZoneRule { alpha2: "GB", standard_minutes: 0, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "IE", standard_minutes: 0, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "PT", standard_minutes: 0, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "IS", standard_minutes: 0, dst: DstRule::None },
+    TimeZoneRule { alpha2: "ES", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "FR", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "DE", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "IT", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "NL", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "BE", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "LU", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "CH", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "AT", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "DK", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "NO", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "SE", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "PL", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "CZ", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "SK", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "HU", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "SI", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "HR", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "RS", standard_minutes: 60, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "FI", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "EE", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "LV", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "LT", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "GR", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "RO", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "BG", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "UA", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "CY", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "TR", standard_minutes: 180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "RU", standard_minutes: 180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "IL", standard_minutes: 120, dst: DstRule::Eu },
+    TimeZoneRule { alpha2: "EG", standard_minutes: 120, dst: DstRule::None },
+    TimeZoneRule { alpha2: "SA", standard_minutes: 180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "AE", standard_minutes: 240, dst: DstRule::None },
+    TimeZoneRule { alpha2: "QA", standard_minutes: 180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "IN", standard_minutes: 330, dst: DstRule::None },
+    TimeZoneRule { alpha2: "PK", standard_minutes: 300, dst: DstRule::None },
+    TimeZoneRule { alpha2: "BD", standard_minutes: 360, dst: DstRule::None },
+    TimeZoneRule { alpha2: "TH", standard_minutes: 420, dst: DstRule::None },
+    TimeZoneRule { alpha2: "VN", standard_minutes: 420, dst: DstRule::None },
+    TimeZoneRule { alpha2: "ID", standard_minutes: 420, dst: DstRule::None },
+    TimeZoneRule { alpha2: "MY", standard_minutes: 480, dst: DstRule::None },
+    TimeZoneRule { alpha2: "SG", standard_minutes: 480, dst: DstRule::None },
+    TimeZoneRule { alpha2: "PH", standard_minutes: 480, dst: DstRule::None },
+    TimeZoneRule { alpha2: "CN", standard_minutes: 480, dst: DstRule::None },
+    TimeZoneRule { alpha2: "HK", standard_minutes: 480, dst: DstRule::None },
+    TimeZoneRule { alpha2: "TW", standard_minutes: 480, dst: DstRule::None },
+    TimeZoneRule { alpha2: "KR", standard_minutes: 540, dst: DstRule::None },
+    TimeZoneRule { alpha2: "JP", standard_minutes: 540, dst: DstRule::None },
+    TimeZoneRule { alpha2: "AU", standard_minutes: 600, dst: DstRule::AuSouth },
+    TimeZoneRule { alpha2: "NZ", standard_minutes: 720, dst: DstRule::Nz },
+    TimeZoneRule { alpha2: "ZA", standard_minutes: 120, dst: DstRule::None },
+    TimeZoneRule { alpha2: "NG", standard_minutes: 60, dst: DstRule::None },
+    TimeZoneRule { alpha2: "KE", standard_minutes: 180, dst: DstRule::None },
+    TimeZoneRule { alpha2: "MA", standard_minutes: 60, dst: DstRule::None },
+];
+
+fn nth_sunday(year: i32, month: u32, n: u32) -> Option<NaiveDate> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let offset = (7 - first.weekday().num_days_from_sunday()) % 7;
+    Some(first + Duration::days((offset + 7 * (n - 1)) as i64))
+}
+
+fn last_sunday(year: i32, month: u32) -> Option<NaiveDate> {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    let last = NaiveDate::from_ymd_opt(next_year, next_month, 1)? - Duration::days(1);
+    Some(last - Duration::days(last.weekday().num_days_from_sunday() as i64))
+}
+
+fn at_hour(date: NaiveDate, hour: i64) -> NaiveDateTime {
+    date.and_time(NaiveTime::MIN) + Duration::hours(hour)
+}
+
+impl TimeZoneRule {
+    pub fn new(country: &Country) -> Option<TimeZoneRule> {
+        RULES.iter().find(|x| x.alpha2 == country.alpha2).copied()
+    }
+
+    /// Whether a local wall-clock time falls inside daylight saving time.
+    /// The hour around each switch is resolved as if it were standard time.
+    pub fn is_dst(&self, local: &NaiveDateTime) -> bool {
+        self.dst_window(local).unwrap_or(false)
+    }
+
+    fn dst_window(&self, local: &NaiveDateTime) -> Option<bool> {
+        let utc = *local - Duration::minutes(self.standard_minutes as i64);
+        let year = local.year();
+
+        match self.dst {
+            DstRule::None => Some(false),
+            DstRule::Eu => {
+                let start = at_hour(last_sunday(year, 3)?, 1);
+                let end = at_hour(last_sunday(year, 10)?, 1);
+                Some(utc >= start && utc < end)
+            },
+            DstRule::Us => {
+                let start = at_hour(nth_sunday(year, 3, 2)?, 2);
+                let end = at_hour(nth_sunday(year, 11, 1)?, 1);
+                Some(*local >= start && *local < end)
+            },
+            DstRule::AuSouth => {
+                let end = at_hour(nth_sunday(year, 4, 1)?, 2);
+                let start = at_hour(nth_sunday(year, 10, 1)?, 2);
+                Some(*local < end || *local >= start)
+            },
+            DstRule::Nz => {
+                let end = at_hour(nth_sunday(year, 4, 1)?, 2);
+                let start = at_hour(last_sunday(year, 9)?, 2);
+                Some(*local < end || *local >= start)
+            },
+        }
+    }
+
+    pub fn offset_at(&self, local: &NaiveDateTime) -> FixedOffset {
+        let dst_minutes = if self.is_dst(local) { 60 } else { 0 };
+        FixedOffset::east_opt((self.standard_minutes + dst_minutes) * 60).unwrap_or(Utc.fix())
+    }
+}
+
+pub fn offset_for_country(country: &Country, local: &NaiveDateTime) -> Option<FixedOffset> {
+    TimeZoneRule::new(country).map(|rule| rule.offset_at(local))
+}
+
+/// The zone to read timestamps without an offset in, in place of each scan's country: a fixed offset,
+/// or one country's zone with its daylight saving rule.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LocalZone {
+    Fixed(FixedOffset),
+    Rule(TimeZoneRule),
+}
+
+impl LocalZone {
+    /// Accepts `UTC`, an offset such as `+02:00`, `-0500` or `+9`, or a country code or name.
+    pub fn new(name: &str) -> Option<LocalZone> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("utc") || name == "Z" {
+            return Some(LocalZone::Fixed(Utc.fix()));
+        }
+
+        let pattern = Regex::new(r"^([+-])(\d{1,2})(?::?(\d{2}))?$").unwrap();
+        if let Some(caps) = pattern.captures(name) {
+            let hours: i32 = caps[2].parse().ok()?;
+            let minutes: i32 = caps.get(3).map_or(Some(0), |x| x.as_str().parse().ok())?;
+            if hours > 14 || minutes > 59 {
+                return None;
+            }
+            let seconds = (hours * 60 + minutes) * 60;
+            return FixedOffset::east_opt(if &caps[1] == "-" { -seconds } else { seconds }).map(LocalZone::Fixed);
+        }
+
+        Country::from_str(name).ok()
+            .and_then(|country| TimeZoneRule::new(&country))
+            .map(LocalZone::Rule)
+    }
+
+    pub fn offset_at(&self, local: &NaiveDateTime) -> FixedOffset {
+        match self {
+            LocalZone::Fixed(offset) => *offset,
+            LocalZone::Rule(rule) => rule.offset_at(local),
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn switch_dates() {
+        assert_eq!(nth_sunday(2017, 3, 2), NaiveDate::from_ymd_opt(2017, 3, 12));
+        assert_eq!(nth_sunday(2017, 11, 1), NaiveDate::from_ymd_opt(2017, 11, 5));
+        assert_eq!(last_sunday(2017, 3), NaiveDate::from_ymd_opt(2017, 3, 26));
+        assert_eq!(last_sunday(2017, 10), NaiveDate::from_ymd_opt(2017, 10, 29));
+    }
+
+    #[test]
+    fn offsets_follow_dst() {
+        let germany = Country::germany();
+        assert_eq!(offset_for_country(&germany, &datetime("2017-01-24 06:45:10")), FixedOffset::east_opt(3600));
+        assert_eq!(offset_for_country(&germany, &datetime("2017-07-24 06:45:10")), FixedOffset::east_opt(7200));
+
+        let usa = Country::the_united_states_of_america();
+        assert_eq!(offset_for_country(&usa, &datetime("2017-03-12 03:00:00")), FixedOffset::west_opt(4 * 3600));
+        assert_eq!(offset_for_country(&usa, &datetime("2017-11-05 01:30:00")), FixedOffset::west_opt(5 * 3600));
+    }
+
+    #[test]
+    fn local_zones() {
+        let local = datetime("2017-07-24 06:45:10");
+        let offset = |name: &str| LocalZone::new(name).map(|x| x.offset_at(&local));
+
+        assert_eq!(offset("UTC"), FixedOffset::east_opt(0));
+        assert_eq!(offset("+02:00"), FixedOffset::east_opt(7200));
+        assert_eq!(offset("-0530"), FixedOffset::west_opt(5 * 3600 + 1800));
+        assert_eq!(offset("+9"), FixedOffset::east_opt(9 * 3600));
+        assert_eq!(offset("DE"), FixedOffset::east_opt(7200));
+        assert_eq!(offset("+25:00"), None);
+        assert_eq!(offset("Atlantis"), None);
+    }
+
+    #[test]
+    fn unknown_country() {
+        assert_eq!(offset_for_country(&Country::antarctica(), &datetime("2017-01-24 06:45:10")), None);
+    }
+}
@@ -0,0 +1,250 @@
+use celes::Country;
+use itertools::Itertools;
+
+use crate::error::Diagnostics;
+use crate::gazetteer::Gazetteer;
+use crate::models::event_kind::EventKind;
+use crate::models::location::Coordinates;
+use crate::models::record::Record;
+use crate::models::shipment::Shipment;
+use crate::models::validation::{Issue, IssueKind, Severity, Validation};
+
+/// Faster than any freight flight, so a change of country that would need it can't be right.
+const MAX_SPEED_KMH: f64 = 1000.0;
+/// Distances are taken between city coordinates, so a short hop across a border is always allowed.
+const BORDER_KM: f64 = 150.0;
+
+fn issue(kind: IssueKind, record: &Record, message: String) -> Issue {
+    Issue::new(kind, record.line, record.tracking.clone(), message)
+}
+
+fn country(record: &Record) -> Option<Country> {
+    record.location.as_ref().and_then(|x| x.country)
+}
+
+/// Where a scan could have happened: its own coordinates, or else every known place in its country.
+fn candidates(record: &Record, gazetteer: &Gazetteer) -> Vec<Coordinates> {
+    match (record.location.as_ref().and_then(|x| x.coordinates), country(record)) {
+        (Some(coordinates), _) => vec![coordinates],
+        (None, Some(country)) => gazetteer.places().iter()
+            .filter(|x| x.country == country)
+            .map(|x| x.coordinates)
+            .collect(),
+        (None, None) => Vec::new(),
+    }
+}
+
+/// The shortest distance two scans can be apart, or `None` when neither can be placed.
+fn min_distance_km(from: &Record, to: &Record, gazetteer: &Gazetteer) -> Option<f64> {
+    let (from, to) = (candidates(from, gazetteer), candidates(to, gazetteer));
+
+    from.iter()
+        .cartesian_product(to.iter())
+        .map(|(a, b)| a.distance_km(b))
+        .min_by(f64::total_cmp)
+}
+
+fn unparseable(diagnostics: &Diagnostics) -> Vec<Issue> {
+    diagnostics.warnings.iter()
+        .map(|x| Issue::new(IssueKind::Unparseable, x.line(), None, x.without_context().to_string()))
+        .collect()
+}
+
+fn duplicates(records: &[&Record]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (index, record) in records.iter().enumerate() {
+        let earlier = records[..index].iter().find(|x| x.datetime == record.datetime && x.status.trim() == record.status.trim());
+        if let Some(earlier) = earlier {
+            issues.push(issue(IssueKind::Duplicate, record, format!("duplicates line {}", earlier.line.unwrap_or_default())));
+        }
+    }
+
+    issues
+}
+
+/// Compares each line with the one above it: going back in local time means the source is out of
+/// order, while going back only once offsets are applied means a scan is in the wrong time zone.
+fn source_order(records: &[&Record]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (before, after) in records.iter().tuple_windows() {
+        let line = before.line.unwrap_or_default();
+        if after.local_datetime() < before.local_datetime() {
+            issues.push(issue(IssueKind::OutOfOrder, after, format!("{} is earlier than line {} ({})", after.local_datetime(), line, before.local_datetime())));
+        } else if after.datetime < before.datetime {
+            let minutes = (after.datetime - before.datetime).num_minutes();
+            issues.push(issue(IssueKind::NegativeLeg, after, format!("the leg from line {} takes {} minutes once time zones are applied", line, minutes)));
+        }
+    }
+
+    issues
+}
+
+/// Checks the legs from each line to the next. Legs that go back in time are left to `source_order`.
+fn legs(records: &[&Record], gazetteer: &Gazetteer) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (before, after) in records.iter().tuple_windows() {
+        let line = before.line.unwrap_or_default();
+        let minutes = (after.datetime - before.datetime).num_minutes();
+        if after.datetime < before.datetime {
+            continue;
+        }
+        if minutes == 0 && before.status.trim() != after.status.trim() {
+            issues.push(issue(IssueKind::ZeroLengthLeg, after, format!("the leg from line {} takes no time", line)));
+        }
+
+        let (from, to) = match (country(before), country(after)) {
+            (Some(from), Some(to)) if from != to => (from, to),
+            _ => continue,
+        };
+        let distance = match min_distance_km(before, after, gazetteer) {
+            Some(distance) if distance > BORDER_KM => distance,
+            _ => continue,
+        };
+        let hours = (after.datetime - before.datetime).num_seconds() as f64 / 3600.0;
+        if hours <= 0.0 || distance / hours > MAX_SPEED_KMH {
+            issues.push(issue(IssueKind::ImpossibleJump, after, format!("{} to {} is at least {:.0} km but only {} minutes after line {}", from.long_name, to.long_name, distance, minutes, line)));
+        }
+    }
+
+    issues
+}
+
+fn unknown_locations(records: &[&Record]) -> Vec<Issue> {
+    records.iter()
+        .filter(|x| country(x).is_none())
+        .map(|x| issue(IssueKind::UnknownLocation, x, format!("no country could be found for '{}'", x.status.trim())))
+        .collect()
+}
+
+fn final_delivery(records: &[&Record]) -> Vec<Issue> {
+    match records.last() {
+        Some(last) if last.kind != EventKind::Delivered => {
+            vec![issue(IssueKind::MissingDelivery, last, format!("the last scan is '{}' ({}), not a delivery", last.status.trim(), last.kind.name()))]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Checks one shipment, line by line. Its records are expected in time order, as the builder leaves
+/// them, so that the last one is the final scan.
+pub fn validate_shipment(shipment: &Shipment, gazetteer: &Gazetteer) -> Vec<Issue> {
+    let by_time: Vec<&Record> = shipment.records.iter().collect();
+    let by_line: Vec<&Record> = by_time.iter().copied().sorted_by_key(|x| x.line).collect();
+
+    let mut issues = duplicates(&by_line);
+    issues.extend(source_order(&by_line));
+    issues.extend(unknown_locations(&by_line));
+    issues.extend(legs(&by_line, gazetteer));
+    issues.extend(final_delivery(&by_time));
+
+    issues
+}
+
+/// Checks every shipment of an input, together with the lines the reader and builder skipped.
+/// Issues are listed by line, with those that have no line last.
+pub fn validate_shipments(file: &str, shipments: &[Shipment], diagnostics: &Diagnostics, gazetteer: &Gazetteer) -> Validation {
+    let mut issues = unparseable(diagnostics);
+    for shipment in shipments {
+        issues.extend(validate_shipment(shipment, gazetteer));
+    }
+    issues.sort_by_key(|x| (x.line.is_none(), x.line));
+
+    let count = |severity: Severity| issues.iter().filter(|x| x.severity == severity).count();
+    Validation {
+        file: file.to_string(),
+        records: shipments.iter().map(|x| x.records.len()).sum(),
+        shipments: shipments.len(),
+        errors: count(Severity::Error),
+        warnings: count(Severity::Warning),
+        issues,
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use chrono::FixedOffset;
+
+    use crate::error::ShipError;
+    use crate::fixtures::sample_records;
+
+    fn kinds(records: Vec<Record>) -> Vec<(IssueKind, Option<usize>)> {
+        validate_shipment(&Shipment::new(None, records), &Gazetteer::default()).iter()
+            .map(|x| (x.kind, x.line))
+            .collect()
+    }
+
+    #[test]
+    fn clean_input() {
+        let shipments = vec![Shipment::new(None, sample_records())];
+        let validation = validate_shipments("test_data.txt", &shipments, &Diagnostics::new(false), &Gazetteer::default());
+
+        assert_eq!(validation.records, 10);
+        assert!(validation.is_valid());
+        assert_eq!(validation.warnings, 1);
+        assert_eq!(validation.issues[0].kind, IssueKind::OutOfOrder);
+        assert_eq!(validation.issues[0].message, "2017-01-23 14:48:08 is earlier than line 5 (2017-01-23 16:02:24)");
+    }
+
+    #[test]
+    fn problems_found() {
+        // The sample has line 6 before line 5. Number the lines in time order so only the changes show.
+        let mut records = sample_records();
+        for (index, record) in records.iter_mut().enumerate() {
+            record.set_line(Some(index + 2));
+        }
+        assert_eq!(kinds(records.clone()), vec![]);
+
+        let mut duplicated = records.clone();
+        let mut copy = duplicated[3].clone();
+        copy.set_line(Some(12));
+        duplicated.insert(4, copy);
+        assert_eq!(kinds(duplicated), vec![(IssueKind::Duplicate, Some(12)), (IssueKind::OutOfOrder, Some(12))]);
+
+        let undelivered = records[..records.len() - 1].to_vec();
+        assert_eq!(kinds(undelivered), vec![(IssueKind::MissingDelivery, Some(10))]);
+
+        let mut swapped = records.clone();
+        swapped[2].set_line(Some(5));
+        swapped[3].set_line(Some(4));
+        assert_eq!(kinds(swapped), vec![(IssueKind::OutOfOrder, Some(5))]);
+
+        // The last US scan a minute before the first German one.
+        let mut jumped = records.clone();
+        let index = jumped.iter().position(|x| country(x).is_some_and(|x| x.alpha2 == "DE")).unwrap();
+        jumped[index - 1].datetime = jumped[index].datetime - chrono::Duration::minutes(1);
+        assert!(kinds(jumped).contains(&(IssueKind::ImpossibleJump, records[index].line)));
+
+        let mut instant = records.clone();
+        instant[9].datetime = instant[8].datetime;
+        assert_eq!(kinds(instant), vec![(IssueKind::ZeroLengthLeg, Some(11))]);
+
+        // Delivered an hour after the last scan by the clock, but five hours east of it.
+        let mut shifted = records.clone();
+        shifted[9].datetime = shifted[8].datetime + chrono::Duration::hours(1);
+        shifted[9].set_offset(FixedOffset::east_opt(5 * 3600).unwrap(), true);
+        shifted.sort_by_key(|x| x.datetime);
+        assert_eq!(kinds(shifted), vec![(IssueKind::NegativeLeg, Some(11)), (IssueKind::MissingDelivery, Some(10))]);
+
+        let mut lost = records.clone();
+        lost[0].set_location(None);
+        assert_eq!(kinds(lost), vec![(IssueKind::UnknownLocation, Some(2))]);
+    }
+
+    #[test]
+    fn skipped_lines() {
+        let mut diagnostics = Diagnostics::new(false);
+        diagnostics.report(ShipError::UnrecognisedRecord("Weather report".to_string()).at_line(4).in_file("feed.txt")).unwrap();
+        let validation = validate_shipments("feed.txt", &[Shipment::new(None, sample_records())], &diagnostics, &Gazetteer::default());
+
+        assert_eq!(validation.errors, 1);
+        assert_eq!(validation.issues[0], Issue::new(IssueKind::Unparseable, Some(4), None, "Unrecognised record: 'Weather report'".to_string()));
+    }
+}
@@ -1,42 +1,114 @@
 use std::fs::File;
 
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
 
-use crate::models::record::Record;
-use crate::formatter::{format_total_shipment_time, format_layover_times, format_longest_delay};
+use serde_json::json;
 
-fn write_total_shipment_time(records: &[Record]) -> String {
-    format!("Total transit time: {}", format_total_shipment_time(records))
+use crate::diff::diff_shipments;
+use crate::error::ShipError;
+use crate::formatter::{format_diff, format_explain, format_portfolio_stats, format_portfolio_summary, format_sla, format_validation};
+use crate::models::{event::{Event, EventLocation}, portfolio::Portfolio, record::Record, report::{OutputFormat, Report, ReportOptions}, shipment::Shipment, sla::SlaEvaluation, validation::Validation};
+use crate::sla::{evaluate_shipments, SlaConfig};
+use crate::render::{render_portfolio_to_string, render_to_string, renderer_for};
+
+pub fn write_report_as(records: &[Record], options: &ReportOptions, format: OutputFormat) -> Result<String, ShipError> {
+    render_to_string(renderer_for(format).as_ref(), &Report::with_options(records, options)?)
+}
+
+/// The report is rendered in full before the file is created, so a failure leaves no half-written report.
+pub fn generate_file_report(records: &[Record], filename: String) -> Result<(), ShipError> {
+    let contents = write_report_as(records, &ReportOptions::default(), OutputFormat::Text)?;
+    let mut file = File::create(&filename).map_err(|err| ShipError::from(err).in_file(&filename))?;
+
+    file.write_all(contents.as_bytes()).map_err(|err| ShipError::from(err).in_file(&filename))
+}
+
+pub fn generate_report(records: &[Record]) -> Result<(), ShipError> {
+    let report = Report::with_options(records, &ReportOptions::default())?;
+    renderer_for(OutputFormat::Text).render(&report, &mut io::stdout().lock())
 }
 
-fn write_layover_times(records: &[Record]) -> String {
-    let fmt_layovers = format_layover_times(records);
-    let header = "Total layover times:\n".to_owned();
-    let body = fmt_layovers.into_iter().collect::<String>();
-    format!("{}{}", header, body)
+pub fn write_portfolio_as(shipments: &[Shipment], options: &ReportOptions, format: OutputFormat) -> Result<String, ShipError> {
+    render_portfolio_to_string(renderer_for(format).as_ref(), &Portfolio::new(shipments, options))
 }
 
-fn write_longest_delay(records: &[Record]) -> String {
-    format!("The longest delay occured:\n{}", format_longest_delay(records))
+/// Evaluates every shipment against the SLA config. Shipments that can't be reported on are left out.
+pub fn evaluate_sla_as(shipments: &[Shipment], config: &SlaConfig, options: &ReportOptions, format: OutputFormat) -> Result<(SlaEvaluation, String), ShipError> {
+    let portfolio = Portfolio::new(shipments, options);
+    let evaluation = evaluate_shipments(config, &portfolio.shipments, options.calendar.as_ref())?;
+    let contents = match format {
+        OutputFormat::Text => format_sla(&evaluation),
+        OutputFormat::Json => serde_json::to_string_pretty(&evaluation)? + "\n",
+        _ => return Err(ShipError::Config("'sla' writes text or json only.".to_string())),
+    };
+
+    Ok((evaluation, contents))
 }
 
-pub fn generate_file_report(records: &[Record], filename: String) {
-    let header ="\n\t*All times formatted [hh:mm]\n\n".to_string(); 
-    let ship_time = write_total_shipment_time(records);
-    let layover = write_layover_times(records);
-    let delay = write_longest_delay(records);
+/// How every record's location was derived. Several shipments each get a heading.
+pub fn explain_as(shipments: &[Shipment], format: OutputFormat) -> Result<String, ShipError> {
+    match format {
+        OutputFormat::Text if shipments.len() == 1 => Ok(format_explain(&shipments[0])),
+        OutputFormat::Text => Ok(shipments.iter()
+            .map(|x| format!("==== {} ====\n{}", x.key(), format_explain(x)))
+            .collect::<Vec<_>>()
+            .join("\n")),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(shipments)? + "\n"),
+        _ => Err(ShipError::Config("'explain' writes text or json only.".to_string())),
+    }
+}
+
+/// The portfolio summary and statistics without the per-shipment reports.
+pub fn stats_as(shipments: &[Shipment], options: &ReportOptions, format: OutputFormat) -> Result<String, ShipError> {
+    let portfolio = Portfolio::new(shipments, options);
+    match format {
+        OutputFormat::Text => Ok(format!("{}\n{}", format_portfolio_summary(&portfolio), format_portfolio_stats(&portfolio.stats))),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(&json!({ "summary": portfolio.summary, "stats": portfolio.stats }))? + "\n"),
+        _ => Err(ShipError::Config("'stats' writes text or json only.".to_string())),
+    }
+}
 
-    let mut file = File::create(filename).unwrap();
-    let contents = format!("{}\n{}\n{}\n{}", &header, &ship_time, &layover, &delay);
+/// How the reports of two inputs differ, shipment by shipment.
+pub fn diff_as(before: &[Shipment], after: &[Shipment], options: &ReportOptions, format: OutputFormat) -> Result<String, ShipError> {
+    let diff = diff_shipments(before, after, options);
+    match format {
+        OutputFormat::Text => Ok(format_diff(&diff)),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(&diff)? + "\n"),
+        _ => Err(ShipError::Config("'diff' writes text or json only.".to_string())),
+    }
+}
 
-    file.write_all(contents.as_bytes()).expect("IO Error");
+/// The problems found in an input, one per line or as a JSON object with the counts.
+pub fn validate_as(validation: &Validation, format: OutputFormat) -> Result<String, ShipError> {
+    match format {
+        OutputFormat::Text => Ok(format_validation(validation)),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(validation)? + "\n"),
+        _ => Err(ShipError::Config("'validate' writes text or json only.".to_string())),
+    }
 }
 
-pub fn generate_report(records: &[Record]) {
-    println!("\n\t*All times formatted [hh:mm]\n\n");
-    println!("{}\n\n", write_total_shipment_time(records));
-    println!("{}\n", write_layover_times(records));
-    println!("{}\n", write_longest_delay(records));
+/// The built records in a normalised form: JSON events the reader takes back as they are, or one CSV
+/// row per scan with the location split into its parts.
+pub fn convert_as(shipments: &[Shipment], format: OutputFormat) -> Result<String, ShipError> {
+    let records = shipments.iter().flat_map(|x| &x.records);
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(&records.map(Event::from_record).collect::<Vec<_>>())? + "\n"),
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(["timestamp", "tracking", "status", "kind", "facility", "city", "region", "postal_code", "country"])?;
+            for event in records.map(Event::from_record) {
+                let location = event.location.unwrap_or(EventLocation { facility: None, city: None, region: None, postal_code: None, country: None });
+                let kind = event.kind.map(|x| x.name()).unwrap_or_default();
+                writer.write_record([Some(event.timestamp), event.tracking, Some(event.status), Some(kind), location.facility, location.city, location.region, location.postal_code, location.country]
+                    .iter()
+                    .map(|x| x.as_deref().unwrap_or("")))?;
+            }
+            let buffer = writer.into_inner().map_err(|err| ShipError::Invalid(err.to_string()))?;
+
+            String::from_utf8(buffer).map_err(|err| ShipError::Invalid(err.to_string()))
+        },
+        _ => Err(ShipError::Config("'convert' writes json or csv only.".to_string())),
+    }
 }
 
 
@@ -44,23 +116,46 @@ pub fn generate_report(records: &[Record]) {
 #[cfg(test)]
 mod tests {
 
-    use crate::{reader::reader, builder::data_builder, models::input::Input};
+    use crate::fixtures::sample_records;
 
     use super::*;
 
     #[test]
     fn test_file() {
-        let inp = Input::new(&["target/debug/shiptracker".to_string(),
-                           "tests/data/test_data.txt".to_string()]).unwrap();
-        let reader = reader(inp).unwrap();
-        let built_data = data_builder(reader).unwrap();
+        let built_data = sample_records();
         let filename = "test.txt".to_string();
 
-        generate_file_report(&built_data, filename);
+        generate_file_report(&built_data, filename).unwrap();
 
         let file = File::open("test.txt");
 
         assert!(file.is_ok())
     }
 
+    #[test]
+    fn empty_report() {
+        assert!(matches!(write_report_as(&[], &ReportOptions::default(), OutputFormat::Text), Err(ShipError::NoRecords)));
+    }
+
+    #[test]
+    fn explain_locations() {
+        let records = sample_records();
+        let customs = records.iter().find(|x| x.line == Some(8)).unwrap();
+        let provenance = customs.location.as_ref().unwrap().provenance.as_ref().unwrap();
+
+        assert!(provenance.inherited);
+        assert_eq!(provenance.inherited_from, Some(7));
+        assert!(provenance.confidence < 0.5);
+
+        let text = explain_as(&[Shipment::new(None, records)], OutputFormat::Text).unwrap();
+        assert!(text.starts_with("Line 2: Shipment picked up"));
+        assert!(text.contains("<- inherited from line 7: \"GERMANY\" (country name)"));
+    }
+
+    #[test]
+    fn validation_summary() {
+        let validation = Validation { file: "feed.txt".to_string(), records: 1, shipments: 1, errors: 0, warnings: 2, issues: Vec::new() };
+        assert_eq!(validate_as(&validation, OutputFormat::Text).unwrap(), "feed.txt: 1 record in 1 shipment, 0 errors, 2 warnings\n");
+    }
+
 }
@@ -1,21 +1,26 @@
 #[test]
 fn text_read_and_build(){
-    let input = shiptracker::models::input::Input::new(&[
-                                                       "target/debug/shiptracker".to_string(),
-                                                       "tests/data/test_data.txt".to_string()]).unwrap();
+    let input = shiptracker::models::input::Input::open("tests/data/test_data.txt", None).unwrap();
 
     let reader = shiptracker::reader::reader(input).unwrap();
     let built_data = shiptracker::builder::data_builder(reader).unwrap();
-    shiptracker::writer::generate_report(&built_data);
+    shiptracker::writer::generate_report(&built_data).unwrap();
 }
 
 #[test]
 fn csv_read_and_build(){
-    let input = shiptracker::models::input::Input::new(&[
-                                                       "target/debug/shiptracker".to_string(),
-                                                       "tests/data/test_data.csv".to_string()]).unwrap();
+    let input = shiptracker::models::input::Input::open("tests/data/test_data.csv", None).unwrap();
 
     let reader = shiptracker::reader::reader(input).unwrap();
     let built_data = shiptracker::builder::data_builder(reader).unwrap();
-    shiptracker::writer::generate_report(&built_data);
+    shiptracker::writer::generate_report(&built_data).unwrap();
+}
+
+#[test]
+fn json_read_and_build(){
+    let input = shiptracker::models::input::Input::open("tests/data/test_data.json", None).unwrap();
+
+    let reader = shiptracker::reader::reader(input).unwrap();
+    let built_data = shiptracker::builder::data_builder(reader).unwrap();
+    shiptracker::writer::generate_report(&built_data).unwrap();
 }